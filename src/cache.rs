@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::CONFIG;
+
+pub fn cache_capacity() -> usize {
+    usize::try_from(
+        CONFIG
+            .get()
+            .unwrap()
+            .get_int("cache_capacity")
+            .expect("Failed to parse cache_capacity as an integer"),
+    )
+    .expect("cache_capacity must not be negative")
+}
+
+pub fn cache_ttl() -> Duration {
+    Duration::from_secs(
+        CONFIG
+            .get()
+            .unwrap()
+            .get_int("cache_ttl")
+            .expect("Failed to parse cache_ttl as an integer")
+            .try_into()
+            .expect("cache_ttl must not be negative"),
+    )
+}
+
+// A map that forgets entries that haven't been inserted in cache_ttl, and its stalest entries once it
+// holds more than cache_capacity, so that in-memory state stays flat no matter how long the bot runs.
+pub struct BoundedMap<K, V> {
+    entries: BTreeMap<K, (Instant, V)>,
+}
+
+impl<K: Ord + Clone, V> BoundedMap<K, V> {
+    pub const fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+        }
+    }
+
+    fn evict(&mut self) {
+        let now = Instant::now();
+        let ttl = cache_ttl();
+        self.entries
+            .retain(|_, (inserted, _)| now.duration_since(*inserted) < ttl);
+        let capacity = cache_capacity();
+        while self.entries.len() > capacity {
+            // Linear, but only runs when the map is already full
+            let stalest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (inserted, _))| *inserted)
+                .map(|(k, _)| k.clone());
+            match stalest {
+                Some(k) => self.entries.remove(&k),
+                None => break,
+            };
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.evict();
+        self.entries.get(key).map(|(_, v)| v)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.evict();
+        self.entries.get_mut(key).map(|(_, v)| v)
+    }
+
+    // Also resets the entry's age.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.entries.insert(key, (Instant::now(), value));
+        self.evict();
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.entries.remove(key).map(|(_, v)| v)
+    }
+
+    pub fn iter(&mut self) -> impl Iterator<Item = (&K, &V)> {
+        self.evict();
+        self.entries.iter().map(|(k, (_, v))| (k, v))
+    }
+}
@@ -1,44 +1,78 @@
-use crate::db::{add_channel, delete_channel, get_num_playlists};
+use crate::cache::BoundedMap;
+use crate::db::{
+    delete_channel, get_channel_playlists, get_guild_default_channel, get_guild_timezone,
+    get_num_guild_subscriptions, get_num_playlists, get_oldest_last_polled, get_playlists,
+    get_subscription_settings, get_subscriptions, set_batch_window, set_guild_default_channel,
+    set_guild_timezone, set_most_recent, set_notify_interval, set_reactions,
+    set_subscription_setting, Subscription, BOOL_SETTINGS,
+};
 use crate::generate_components::make_button;
-use crate::youtube::{get_upload_playlist_id, PlaylistIdError};
-use crate::{ADMIN_USERS, TIME_PER_REQUEST};
+use crate::log_limit::{log_error, log_info};
+use crate::update_loop::{
+    boost_playlist, get_failures, maintenance, maintenance_hold, maintenance_mode,
+    process_single_playlist, resend_latest, set_maintenance,
+};
+use crate::youtube::{
+    get_extras_by_id, get_playlist_preview, get_upload_playlist_id, get_video_channel_id,
+    parse_playlist_id, parse_video_id, trace_upload_playlist_id, uploads_playlist_id, ExtrasError,
+    PlaylistIdError, YoutubeApi,
+};
+use crate::{ADMIN_USERS, CONFIG, TIME_PER_REQUEST, YOUTUBE};
 
+use std::collections::BTreeMap;
 use std::time::{Duration, Instant};
 
+use chrono_tz::Tz;
+use google_youtube3::chrono::{DateTime, Utc};
+
 use serenity::all::{
-    CommandInteraction, CommandOptionType, Context, CreateActionRow, CreateCommand,
-    CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage,
-    EditInteractionResponse, ResolvedValue,
+    ChannelId, ChannelType, CommandInteraction, CommandOptionType, Context, CreateActionRow,
+    CreateAllowedMentions, CreateAttachment, CreateCommand, CreateCommandOption, CreateEmbed,
+    CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
+    EditInteractionResponse, GuildId, Mentionable, Permissions, ReactionType, ResolvedOption,
+    ResolvedValue, UserId,
 };
 use serenity::model::prelude::ButtonStyle;
 use serenity::prelude::SerenityError;
 
 // needed for shutdown command
-use tokio::sync::{mpsc::Sender, OnceCell};
+use tokio::sync::{mpsc::Sender, Mutex, OnceCell};
 
 pub static SHUTDOWN_SENDER: OnceCell<Sender<bool>> = OnceCell::const_new();
 
-async fn send_simple_response_message<D>(
+// The builder closures let callers attach embeds, components, attachments, etc.
+// without repeating the response boilerplate. The _simple versions cover the common content-only case.
+async fn send_response_message<F>(
     ctx: &Context,
     command: &CommandInteraction,
-    content: D,
     ephemeral: bool,
+    build: F,
 ) -> Result<(), SerenityError>
 where
-    D: Into<String>,
+    F: FnOnce(CreateInteractionResponseMessage) -> CreateInteractionResponseMessage,
 {
     command
         .create_response(
             &ctx.http,
-            CreateInteractionResponse::Message(
-                CreateInteractionResponseMessage::new()
-                    .content(content)
-                    .ephemeral(ephemeral),
-            ),
+            CreateInteractionResponse::Message(build(
+                CreateInteractionResponseMessage::new().ephemeral(ephemeral),
+            )),
         )
         .await
 }
 
+async fn send_simple_response_message<D>(
+    ctx: &Context,
+    command: &CommandInteraction,
+    content: D,
+    ephemeral: bool,
+) -> Result<(), SerenityError>
+where
+    D: Into<String>,
+{
+    send_response_message(ctx, command, ephemeral, |m| m.content(content)).await
+}
+
 async fn simple_defer(
     ctx: &Context,
     command: &CommandInteraction,
@@ -54,20 +88,92 @@ async fn simple_defer(
         .await
 }
 
-async fn edit_deferred_message_simple<D>(
+async fn edit_deferred_message<F>(
     ctx: &Context,
     command: &CommandInteraction,
-    content: D,
+    build: F,
 ) -> Result<(), SerenityError>
 where
-    D: Into<String>,
+    F: FnOnce(EditInteractionResponse) -> EditInteractionResponse,
 {
     command
-        .edit_response(&ctx.http, EditInteractionResponse::new().content(content))
+        .edit_response(&ctx.http, build(EditInteractionResponse::new()))
         .await?;
     Ok(())
 }
 
+async fn edit_deferred_message_simple<D>(
+    ctx: &Context,
+    command: &CommandInteraction,
+    content: D,
+) -> Result<(), SerenityError>
+where
+    D: Into<String>,
+{
+    edit_deferred_message(ctx, command, |r| r.content(content)).await
+}
+
+// Every boolean subscription setting is a /config subcommand taking the same two options.
+fn bool_setting_subcommand(name: &str, description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::SubCommand, name, description)
+        .add_sub_option(
+            CreateCommandOption::new(
+                CommandOptionType::String,
+                "channel_url",
+                "Url of the YouTube channel",
+            )
+            .required(true),
+        )
+        .add_sub_option(
+            CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "enabled",
+                "Whether to turn this setting on or off",
+            )
+            .required(true),
+        )
+}
+
+// Commands that change what gets posted where are only shown to members who could manage the channel themselves.
+// Server admins can still override this per command in Server Settings > Integrations.
+pub const MANAGE_PERMISSIONS: Permissions = Permissions::MANAGE_CHANNELS;
+
+// 0 means no limit.
+pub fn max_subscriptions_per_guild() -> u32 {
+    u32::try_from(
+        CONFIG
+            .get()
+            .unwrap()
+            .get_int("max_subscriptions_per_guild")
+            .expect("Failed to parse max_subscriptions_per_guild as an integer"),
+    )
+    .expect("max_subscriptions_per_guild must not be negative")
+}
+
+// Returns why a new subscription in this guild should be refused, if it should be.
+// DMs don't count towards any guild.
+pub async fn guild_limit_reached(guild_id: Option<GuildId>) -> Option<String> {
+    let max = max_subscriptions_per_guild();
+    let guild_id = guild_id.filter(|_| max != 0)?;
+    match get_num_guild_subscriptions(guild_id).await {
+        Ok(n) if n >= max => Some(format!(
+            "This server already has {} subscriptions, which is the most this bot allows per server. Unsubscribe from something first.",
+            n
+        )),
+        Ok(_) => None,
+        Err(e) => Some(format!("Failed to count this server's subscriptions: {}", e)),
+    }
+}
+
+// One week.
+const MAX_NOTIFY_INTERVAL_MINUTES: u64 = 7 * 24 * 60;
+const MAX_BATCH_WINDOW_MINUTES: u64 = 24 * 60;
+// Each one is a separate request to Discord for every notification, so keep it to a handful.
+const MAX_REACTIONS: usize = 5;
+
+// Every watched playlist takes requests away from all the others, so don't let it go on forever.
+const MAX_WATCH_MINUTES: u64 = 120;
+
 pub fn create_commands() -> Vec<CreateCommand> {
     // DON'T FORGET to add your custom commands here!!
     vec![
@@ -76,6 +182,7 @@ pub fn create_commands() -> Vec<CreateCommand> {
         CreateCommand::new("shutdown").description("Shut down the bot"),
         CreateCommand::new("subscribe")
             .description("Receive notifications from a YouTube channel in this channel")
+            .default_member_permissions(MANAGE_PERMISSIONS)
             .add_option(
                 CreateCommandOption::new(
                     CommandOptionType::String,
@@ -83,9 +190,200 @@ pub fn create_commands() -> Vec<CreateCommand> {
                     "Url of the YouTube channel",
                 )
                 .required(true),
+            )
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "here",
+                "Use this channel even if the server has a home channel set with /movehome",
+            ))
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Channel,
+                    "also",
+                    "Another channel to send the same notifications to",
+                )
+                .channel_types(vec![
+                    ChannelType::Text,
+                    ChannelType::News,
+                    ChannelType::Forum,
+                ]),
+            )
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Channel,
+                    "also_2",
+                    "Yet another channel to send the same notifications to",
+                )
+                .channel_types(vec![
+                    ChannelType::Text,
+                    ChannelType::News,
+                    ChannelType::Forum,
+                ]),
             ),
+        CreateCommand::new("movehome")
+            .description("Show or set the channel that /subscribe uses by default in this server")
+            .default_member_permissions(MANAGE_PERMISSIONS)
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Channel,
+                    "channel",
+                    "Channel for new subscriptions. Leave empty to use wherever /subscribe is run",
+                )
+                .channel_types(vec![
+                    ChannelType::Text,
+                    ChannelType::News,
+                    ChannelType::Forum,
+                ]),
+            )
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "clear",
+                "Stop using a home channel",
+            )),
         CreateCommand::new("unsubscribe")
             .description("Stop receiving notifications from a YouTube channel in this channel")
+            .default_member_permissions(MANAGE_PERMISSIONS)
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "channel_url",
+                "Url of the YouTube channel",
+            ))
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "index",
+                    "Number of the subscription in /list, instead of a url",
+                )
+                .min_int_value(1),
+            ),
+        CreateCommand::new("list")
+            .description("List this channel's subscriptions"),
+        CreateCommand::new("settings")
+            .description("Show every setting for one of this channel's subscriptions")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "channel_url",
+                    "Url of the YouTube channel",
+                )
+                .required(true),
+            ),
+        CreateCommand::new("howmany").description("Print how many playlists are being tracked, and how frequently each playlist is checked"),
+        CreateCommand::new("timezone")
+            .description("Show or set the timezone used when displaying times in this server")
+            .default_member_permissions(MANAGE_PERMISSIONS)
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "timezone",
+                "IANA timezone name, e.g. America/New_York",
+            )),
+        CreateCommand::new("export_csv")
+            .description("Export this server's subscriptions as a CSV file"),
+        CreateCommand::new("poll")
+            .description("Check a YouTube channel for new uploads right now")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "channel_url",
+                    "Url of the YouTube channel",
+                )
+                .required(true),
+            ),
+        CreateCommand::new("resolve")
+            .description("Show each step of how a channel url resolves to an uploads playlist")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "channel_url",
+                    "Url of the YouTube channel",
+                )
+                .required(true),
+            ),
+        CreateCommand::new("failures")
+            .description("List the playlists whose most recent poll failed"),
+        CreateCommand::new("maintenance")
+            .description("Pause or resume sending notifications, without shutting down")
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "enabled",
+                "Whether to pause notifications. Leave this out to see whether they're paused",
+            )),
+        CreateCommand::new("setpollrate")
+            .description("Change how long the bot waits between YouTube API requests")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "milliseconds",
+                    "Delay between requests. Lower values check channels more often",
+                )
+                .min_int_value(TIME_PER_REQUEST.as_millis() as u64)
+                .required(true),
+            ),
+        CreateCommand::new("inspect")
+            .description("Show how the bot would treat a video, without sending anything")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "video",
+                    "Url or id of the YouTube video",
+                )
+                .required(true),
+            ),
+        CreateCommand::new("setcursor")
+            .description("Set when this channel's subscription last saw an upload. Rewinding replays videos!")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "channel_url",
+                    "Url of the YouTube channel",
+                )
+                .required(true),
+            )
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "timestamp",
+                    "RFC 3339 timestamp (e.g. 2024-08-20T12:00:00Z), or \"now\"",
+                )
+                .required(true),
+            ),
+        CreateCommand::new("resetcursors")
+            .description("Skip everything uploaded before now, for every subscription in this channel"),
+        CreateCommand::new("watch")
+            .description("Check a YouTube channel as often as possible for a while, e.g. right before a premiere")
+            .default_member_permissions(MANAGE_PERMISSIONS)
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "channel_url",
+                    "Url of the YouTube channel",
+                )
+                .required(true),
+            )
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "minutes",
+                    "How long to keep checking it",
+                )
+                .min_int_value(1)
+                .max_int_value(MAX_WATCH_MINUTES)
+                .required(true),
+            ),
+        CreateCommand::new("report")
+            .description("Send a message to the people running this bot, e.g. to report a problem")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "message",
+                    "What you'd like to tell them",
+                )
+                .max_length(MAX_REPORT_LENGTH)
+                .required(true),
+            ),
+        CreateCommand::new("resend")
+            .description("Send the notification for a channel's latest video again")
+            .default_member_permissions(MANAGE_PERMISSIONS)
             .add_option(
                 CreateCommandOption::new(
                     CommandOptionType::String,
@@ -94,7 +392,149 @@ pub fn create_commands() -> Vec<CreateCommand> {
                 )
                 .required(true),
             ),
-        CreateCommand::new("howmany").description("Print how many playlists are being tracked, and how frequently each playlist is checked")
+        CreateCommand::new("setfilters_all")
+            .description("Change a setting for every subscription in this channel at once")
+            .default_member_permissions(MANAGE_PERMISSIONS)
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "setting",
+                    "Which setting to change",
+                )
+                // Values are column names, which set_channel_setting checks against BOOL_SETTINGS
+                .add_string_choice("Announce unlisted videos", "unlisted_allowed")
+                .add_string_choice("Hide video previews", "suppress_embeds")
+                .add_string_choice("Show view and like counts", "show_stats")
+                .add_string_choice("Welcome line on the next notification", "awaiting_first")
+                .add_string_choice("Skip age-restricted videos", "skip_age_restricted")
+                .add_string_choice("Alert when a stream goes live", "live_alerts")
+                .add_string_choice("Skip re-uploads with a recently used title", "dedup_titles")
+                .add_string_choice("Post in a thread per creator", "use_thread")
+                .add_string_choice("List the video's chapters", "show_chapters")
+                .add_string_choice("Send a daily digest instead", "daily_digest")
+                .required(true),
+            )
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "enabled",
+                    "Whether to turn this setting on or off",
+                )
+                .required(true),
+            ),
+        CreateCommand::new("config")
+            .description("Change a setting for one of this channel's subscriptions")
+            .default_member_permissions(MANAGE_PERMISSIONS)
+            .add_option(bool_setting_subcommand(
+                "unlisted",
+                "Announce unlisted videos (never announced by default)",
+            ))
+            .add_option(bool_setting_subcommand(
+                "no_embeds",
+                "Post just the link, without Discord's video preview",
+            ))
+            .add_option(bool_setting_subcommand(
+                "stats",
+                "Show the video's view and like counts at the time it's announced",
+            ))
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "interval",
+                    "Send at most one message per this many minutes, listing anything that came in between",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "channel_url",
+                        "Url of the YouTube channel",
+                    )
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Integer,
+                        "minutes",
+                        "Minimum minutes between messages, or 0 to send every video right away",
+                    )
+                    .min_int_value(0)
+                    .max_int_value(MAX_NOTIFY_INTERVAL_MINUTES)
+                    .required(true),
+                ),
+            )
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "batch",
+                    "Wait this many minutes after a video comes out, to send it together with any that follow",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "channel_url",
+                        "Url of the YouTube channel",
+                    )
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Integer,
+                        "minutes",
+                        "How long to wait, or 0 to send every video right away",
+                    )
+                    .min_int_value(0)
+                    .max_int_value(MAX_BATCH_WINDOW_MINUTES)
+                    .required(true),
+                ),
+            )
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "reactions",
+                    "React to each notification with these emojis, e.g. for a quick poll",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "channel_url",
+                        "Url of the YouTube channel",
+                    )
+                    .required(true),
+                )
+                .add_sub_option(CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "emojis",
+                    "Emojis separated by spaces. Leave this out to stop reacting",
+                )),
+            )
+            .add_option(bool_setting_subcommand(
+                "no_age_restricted",
+                "Don't announce age-restricted videos",
+            ))
+            .add_option(bool_setting_subcommand(
+                "live",
+                "Also check whether the channel is streaming, to alert as soon as it goes live",
+            ))
+            .add_option(bool_setting_subcommand(
+                "dedup",
+                "Skip videos with the same title as one announced recently, e.g. re-uploads",
+            ))
+            .add_option(bool_setting_subcommand(
+                "chapters",
+                "List the first few chapters from the video's description, if it has any",
+            ))
+            .add_option(bool_setting_subcommand(
+                "digest",
+                "Collect this creator's videos into one message a day, at the bot's digest time",
+            ))
+            .add_option(bool_setting_subcommand(
+                "thread",
+                "Post this creator's videos in a thread of their own within this channel",
+            ))
+            .add_option(bool_setting_subcommand(
+                "welcome",
+                "Say which channel this is for in the next notification, to confirm it works",
+            )),
     ]
 }
 // Any custom slash commands must be added both to create_commands ^^^ and to handle_command!!
@@ -109,7 +549,25 @@ pub async fn handle_command(
         "shutdown" => shutdown_command(ctx, command).await,
         "subscribe" => subscribe_command(ctx, command).await,
         "unsubscribe" => unsubscribe_command(ctx, command).await,
+        "movehome" => movehome_command(ctx, command).await,
+        "list" => list_command(ctx, command).await,
+        "settings" => settings_command(ctx, command).await,
         "howmany" => howmany_command(ctx, command).await,
+        "timezone" => timezone_command(ctx, command).await,
+        "export_csv" => export_csv_command(ctx, command).await,
+        "poll" => poll_command(ctx, command).await,
+        "inspect" => inspect_command(ctx, command).await,
+        "resolve" => resolve_command(ctx, command).await,
+        "setpollrate" => setpollrate_command(ctx, command).await,
+        "failures" => failures_command(ctx, command).await,
+        "maintenance" => maintenance_command(ctx, command).await,
+        "config" => config_command(ctx, command).await,
+        "setfilters_all" => setfilters_all_command(ctx, command).await,
+        "setcursor" => setcursor_command(ctx, command).await,
+        "resetcursors" => resetcursors_command(ctx, command).await,
+        "watch" => watch_command(ctx, command).await,
+        "resend" => resend_command(ctx, command).await,
+        "report" => report_command(ctx, command).await,
         _ => nyi_command(ctx, command).await,
     }
 }
@@ -129,7 +587,8 @@ async fn help_command(ctx: Context, command: CommandInteraction) -> Result<(), S
     send_simple_response_message(
         &ctx,
         &command,
-        "Currently available commands: `/ping`, `/shutdown`, `/help`.",
+        "Currently available commands: `/subscribe`, `/unsubscribe`, `/list`, `/settings`, `/config`, `/setfilters_all`, `/movehome`, `/resend`, `/watch`, `/timezone`, `/howmany`, `/inspect`, `/report`, `/ping`, `/help`.\n\
+        Bot admins only: `/poll`, `/failures`, `/resolve`, `/setcursor`, `/resetcursors`, `/setpollrate`, `/export_csv`, `/maintenance`, `/shutdown`.",
         true,
     )
     .await
@@ -143,36 +602,41 @@ async fn ping_command(ctx: Context, command: CommandInteraction) -> Result<(), S
     simple_defer(&ctx, &command, true).await?;
     let mut duration = start_time.elapsed().as_millis().to_string();
     duration.push_str(" ms");
-    command
-        .edit_response(
-            &ctx.http,
-            EditInteractionResponse::new()
-                .content(duration)
-                .components(vec![CreateActionRow::Buttons(vec![make_button(
-                    "refresh_ping",
-                    ButtonStyle::Secondary,
-                    Some('🔄'),
-                    None,
-                    false,
-                )])]),
-        )
-        .await?;
-    Ok(())
+    edit_deferred_message(&ctx, &command, |r| {
+        r.content(duration)
+            .components(vec![CreateActionRow::Buttons(vec![make_button(
+                "refresh_ping",
+                ButtonStyle::Secondary,
+                Some('🔄'),
+                None,
+                false,
+            )])])
+    })
+    .await
 }
 
-async fn shutdown_command(ctx: Context, command: CommandInteraction) -> Result<(), SerenityError> {
+fn is_admin(command: &CommandInteraction) -> bool {
+    is_admin_user(command.user.id)
+}
+
+// Components need this too, since anyone who can see a confirm button can click it.
+pub fn is_admin_user(user_id: UserId) -> bool {
     // Set your admin user list in your config file
     let admins = ADMIN_USERS
         .get()
         .expect("Admin list somehow uninitialized??");
-    if !admins.is_empty() && !admins.contains(&command.user.id) {
+    admins.is_empty() || admins.contains(&user_id)
+}
+
+async fn shutdown_command(ctx: Context, command: CommandInteraction) -> Result<(), SerenityError> {
+    if !is_admin(&command) {
         send_simple_response_message(&ctx, &command, "You do not have permission.", true).await?;
         return Ok(());
     }
-    println!(
+    log_info(format!(
         "Shutdown from user {} with Id {}",
         command.user.name, command.user.id
-    );
+    ));
     // no ? here, we don't want to return early if this fails
     _ = send_simple_response_message(&ctx, &command, "Shutting down...", true).await;
     // originally loosely based on https://stackoverflow.com/a/65456463
@@ -185,7 +649,7 @@ async fn shutdown_command(ctx: Context, command: CommandInteraction) -> Result<(
         .send(true)
         .await
         .expect("Shutdown message send error");
-    println!("Passed shutdown message");
+    log_info("Passed shutdown message".to_string());
     // I'm pretty sure this is unnecessary but it makes me happier than not doing it
     ctx.shard.shutdown_clean();
     Ok(())
@@ -208,91 +672,511 @@ async fn get_playlist_id_from_url<'a>(
         }
     };
 
+    match resolve_playlist_id(channel_url).await {
+        Ok(playlist_id) => Ok(playlist_id),
+        Err(message) => Err(edit_deferred_message_simple(&ctx, &command, message).await),
+    }
+}
+
+// Err is the message to show the user.
+pub async fn resolve_playlist_id(channel_url: &str) -> Result<String, String> {
+    if let Some(playlist_id) = parse_playlist_id(channel_url) {
+        return Ok(playlist_id);
+    }
+
+    // "Subscribe to whoever made this video" is a lot easier than finding the channel's url first
+    if let Some(video_id) = parse_video_id(channel_url) {
+        return match get_video_channel_id(&video_id).await {
+            Ok(Some(channel_id)) => uploads_playlist_id(&channel_id).ok_or_else(|| {
+                format!("YouTube returned an unexpected channel id: {}", channel_id)
+            }),
+            Ok(None) => Err(format!("Couldn't find a video with id {}.", video_id)),
+            Err(e) => Err(format!("Failed to look up the video's channel: {}", e)),
+        };
+    }
+
     match get_upload_playlist_id(channel_url).await {
         Ok(v) => Ok(v),
-        Err(PlaylistIdError::BadStatus(status)) => Err(edit_deferred_message_simple(
-            &ctx,
-            &command,
-            format!("HTTP request returned bad status code: {}", status),
-        )
-        .await),
-        Err(PlaylistIdError::BodyParseError(e)) => Err(edit_deferred_message_simple(
-            &ctx,
-            &command,
-            format!(
-                "Could not find channel ID on webpage at webpage with address: \"{}\"",
-                e
-            ),
-        )
-        .await),
-        Err(PlaylistIdError::Hyper(e)) => {
-            Err(edit_deferred_message_simple(&ctx, &command, format!("HTTP Error: {}", e)).await)
-        }
+        Err(PlaylistIdError::BadStatus(status)) => Err(format!(
+            "HTTP request returned bad status code: {}",
+            status
+        )),
+        Err(PlaylistIdError::BodyParseError(e)) => Err(format!(
+            "Could not find channel ID on webpage at webpage with address: \"{}\"",
+            e
+        )),
+        Err(PlaylistIdError::Hyper(e)) => Err(format!("HTTP Error: {}", e)),
 
-        Err(PlaylistIdError::UriParseError(_)) => Err(edit_deferred_message_simple(
-            &ctx,
-            &command,
-            format!(
-                "Invalid URL. Please make sure you typed it correctly.\nRecieved: {}",
-                channel_url
-            ),
-        )
-        .await),
+        Err(PlaylistIdError::NotAChannelUrl(url)) => Err(format!(
+            "That URL doesn't point to a YouTube channel. Try the link to the channel's page instead.\nRecieved: {}",
+            url
+        )),
+        Err(PlaylistIdError::NotYouTube(url)) => Err(format!(
+            "That isn't a YouTube URL. Please use the link to the channel's page on youtube.com.\nRecieved: {}",
+            url
+        )),
+        Err(PlaylistIdError::UriParseError(_)) => Err(format!(
+            "Invalid URL. Please make sure you typed it correctly.\nRecieved: {}",
+            channel_url
+        )),
     }
 }
 
+// Discord rejects custom_ids longer than this.
+const MAX_CUSTOM_ID_LENGTH: usize = 100;
+
+// Lets the user re-run whatever failed with one click, since most failures here are transient.
+// None if the payload doesn't fit in a custom_id, in which case they'll have to type the command again.
+pub fn retry_row(custom_id: String) -> Option<CreateActionRow> {
+    (custom_id.len() <= MAX_CUSTOM_ID_LENGTH).then(|| {
+        CreateActionRow::Buttons(vec![make_button(
+            custom_id,
+            ButtonStyle::Secondary,
+            Some('🔁'),
+            Some("Retry"),
+            false,
+        )])
+    })
+}
+
 async fn subscribe_command(ctx: Context, command: CommandInteraction) -> Result<(), SerenityError> {
     simple_defer(&ctx, &command, true).await?;
 
-    let playlist_id =
-        match get_playlist_id_from_url(&command.data.options()[0].value, &ctx, &command).await {
-            Ok(s) => s,
-            Err(e) => return e,
-        };
+    let options = command.data.options();
+    let channel_url = match find_option(&options, "channel_url") {
+        Some(ResolvedValue::String(s)) => *s,
+        _ => return edit_deferred_message_simple(&ctx, &command, "Missing channel url.").await,
+    };
 
-    match add_channel(&playlist_id, command.channel_id).await {
-        Ok(_) => {
-            edit_deferred_message_simple(
-                &ctx,
-                &command,
-                format!(
-                    "Successfully subscribed channel {} to uploads playlist {}.",
-                    command.channel_id.get(),
-                    playlist_id
-                ),
+    if let Some(reason) = guild_limit_reached(command.guild_id).await {
+        return edit_deferred_message_simple(&ctx, &command, reason).await;
+    }
+
+    let here = matches!(
+        find_option(&options, "here"),
+        Some(ResolvedValue::Boolean(true))
+    );
+    let target_channel = match command.guild_id {
+        Some(guild_id) if !here => match get_guild_default_channel(guild_id).await {
+            Ok(home) => home.unwrap_or(command.channel_id),
+            Err(e) => {
+                return edit_deferred_message_simple(
+                    &ctx,
+                    &command,
+                    format!("Failed to read home channel from database: {}", e),
+                )
+                .await
+            }
+        },
+        _ => command.channel_id,
+    };
+    // The url is only resolved once, no matter how many channels it goes to.
+    // More than 3 channels wouldn't fit in the button's custom_id.
+    let mut target_channels = vec![target_channel];
+    for name in ["also", "also_2"] {
+        if let Some(ResolvedValue::Channel(channel)) = find_option(&options, name) {
+            if !target_channels.contains(&channel.id) {
+                target_channels.push(channel.id);
+            }
+        }
+    }
+
+    let prompt = match resolve_playlist_id(channel_url).await {
+        Ok(playlist_id) => {
+            subscribe_prompt(
+                &playlist_id,
+                &target_channels,
+                command.channel_id,
+                command.guild_id.is_some(),
+                parse_playlist_id(channel_url).is_some(),
             )
             .await
         }
-        Err(e) => {
-            edit_deferred_message_simple(
-                &ctx,
-                &command,
-                format!("Failed to add entry to database: {}", e),
-            )
+        Err(message) => Err(message),
+    };
+    match prompt {
+        Ok((content, row)) => {
+            edit_deferred_message(&ctx, &command, |r| r.content(content).components(vec![row]))
+                .await
+        }
+        Err(message) => {
+            let retry = retry_row(format!(
+                "retry:subscribe:{}:{}",
+                join_channel_ids(&target_channels),
+                channel_url
+            ));
+            edit_deferred_message(&ctx, &command, |r| {
+                r.content(message).components(retry.into_iter().collect())
+            })
             .await
         }
     }
 }
 
-async fn unsubscribe_command(
-    ctx: Context,
-    command: CommandInteraction,
-) -> Result<(), SerenityError> {
-    simple_defer(&ctx, &command, true).await?;
+pub fn join_channel_ids(channel_ids: &[ChannelId]) -> String {
+    channel_ids
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
 
-    let playlist_id =
-        match get_playlist_id_from_url(&command.data.options()[0].value, &ctx, &command).await {
-            Ok(s) => s,
-            Err(e) => return e,
-        };
+// The preview of what a url resolved to, with the button that actually subscribes.
+// current_channel is where the prompt is shown, so it can be called "this channel".
+pub async fn subscribe_prompt(
+    playlist_id: &String,
+    target_channels: &[ChannelId],
+    current_channel: ChannelId,
+    in_guild: bool,
+    explicit_id: bool,
+) -> Result<(String, CreateActionRow), String> {
+    // URLs don't always resolve to the channel you'd expect, so show what it resolved to before committing.
+    let preview = get_playlist_preview(playlist_id)
+        .await
+        .map_err(|e| format!("Failed to look up uploads playlist {}: {}", playlist_id, e))?;
+    // An explicit playlist id skipped resolving entirely, so this is the only check that it's real.
+    if explicit_id && preview.latest_video_id.is_none() {
+        return Err(format!(
+            "Playlist {} is empty, private, or doesn't exist.",
+            playlist_id
+        ));
+    }
 
-    match delete_channel(&playlist_id, command.channel_id).await {
-        Ok(_) => {
-            edit_deferred_message_simple(
+    // A DM has a channel id like any other, so subscribing there works the same way.
+    let target = target_channels
+        .iter()
+        .map(|&c| {
+            if !in_guild {
+                "your DMs".to_string()
+            } else if c == current_channel {
+                "this channel".to_string()
+            } else {
+                c.mention().to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut content = format!(
+        "Subscribe {} to **{}**?\nLatest upload: ",
+        target,
+        preview.channel_title.as_deref().unwrap_or(playlist_id)
+    );
+    match preview.latest_video_id {
+        Some(id) => content.push_str(&format!(
+            "{} https://youtu.be/{}",
+            preview.latest_video_title.unwrap_or_default(),
+            id
+        )),
+        // Still allowed, since a brand new channel may just not have uploaded yet.
+        // But it's more often a typo, or a url that resolved to the wrong channel.
+        None => content.push_str(
+            "(none)\n⚠️ This channel has no public uploads. Did you mean a different channel?",
+        ),
+    }
+
+    Ok((
+        content,
+        CreateActionRow::Buttons(vec![make_button(
+            format!(
+                "subscribe:{}:{}",
+                playlist_id,
+                join_channel_ids(target_channels)
+            ),
+            ButtonStyle::Success,
+            Some('✅'),
+            Some("Confirm"),
+            false,
+        )]),
+    ))
+}
+
+// Discord rejects messages longer than this.
+pub const MAX_MESSAGE_LENGTH: usize = 2000;
+
+async fn list_command(ctx: Context, command: CommandInteraction) -> Result<(), SerenityError> {
+    simple_defer(&ctx, &command, true).await?;
+
+    let playlists = match get_channel_playlists(command.channel_id).await {
+        Ok(v) => v,
+        Err(e) => {
+            return edit_deferred_message_simple(
+                &ctx,
+                &command,
+                format!("Failed to get subscriptions from database: {}", e),
+            )
+            .await
+        }
+    };
+
+    if playlists.is_empty() {
+        return edit_deferred_message_simple(
+            &ctx,
+            &command,
+            "This channel isn't subscribed to anything.",
+        )
+        .await;
+    }
+
+    let mut content = String::new();
+    for (i, playlist_id) in playlists.iter().enumerate() {
+        // The reverse of uploads_playlist_id, so the link goes to the channel itself.
+        // Playlists subscribed to by id might not be anyone's uploads, so those link to the playlist.
+        let line = match playlist_id.strip_prefix("UU") {
+            Some(suffix) => format!(
+                "`{}.` https://www.youtube.com/channel/UC{}\n",
+                i + 1,
+                suffix
+            ),
+            None => format!(
+                "`{}.` https://www.youtube.com/playlist?list={}\n",
+                i + 1,
+                playlist_id
+            ),
+        };
+        // Leave room for the note about the rest
+        if content.len() + line.len() > MAX_MESSAGE_LENGTH - 50 {
+            content.push_str(&format!("...and {} more.", playlists.len() - i));
+            break;
+        }
+        content.push_str(&line);
+    }
+    edit_deferred_message_simple(&ctx, &command, content).await
+}
+
+fn on_off(b: bool) -> &'static str {
+    if b {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+// The read side of /config, /setfilters_all and /setcursor.
+async fn settings_command(ctx: Context, command: CommandInteraction) -> Result<(), SerenityError> {
+    simple_defer(&ctx, &command, true).await?;
+
+    let options = command.data.options();
+    let url = match find_option(&options, "channel_url") {
+        Some(url) => url,
+        None => {
+            return edit_deferred_message_simple(&ctx, &command, "Missing required options.").await
+        }
+    };
+    let playlist_id = match get_playlist_id_from_url(url, &ctx, &command).await {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+
+    let (most_recent, settings) =
+        match get_subscription_settings(&playlist_id, &command.channel_id).await {
+            Ok(Some(s)) => s,
+            Ok(None) => {
+                return edit_deferred_message_simple(
+                    &ctx,
+                    &command,
+                    format!(
+                        "Channel {} isn't subscribed to uploads playlist {}.",
+                        command.channel_id.get(),
+                        playlist_id
+                    ),
+                )
+                .await
+            }
+            Err(e) => {
+                return edit_deferred_message_simple(
+                    &ctx,
+                    &command,
+                    format!("Failed to read settings from database: {}", e),
+                )
+                .await
+            }
+        };
+
+    let interval = match settings.notify_interval {
+        0 => "off, every video is sent right away".to_string(),
+        n => match settings.last_notified {
+            Some(t) => format!("{} minutes, last sent <t:{}:f>", n, t.timestamp()),
+            None => format!("{} minutes", n),
+        },
+    };
+    let thread = match (settings.use_thread, settings.thread_id) {
+        (false, _) => "off".to_string(),
+        (true, Some(thread_id)) => format!("on, in {}", thread_id.mention()),
+        (true, None) => "on, created with the next notification".to_string(),
+    };
+    // Field names match the /config subcommands that change them
+    let embed = CreateEmbed::new()
+        .title(format!("Settings for {}", playlist_id))
+        .description(format!(
+            "Videos published after <t:{}:f> will be sent.",
+            most_recent.timestamp()
+        ))
+        .field("unlisted", on_off(settings.unlisted_allowed), true)
+        .field("no_embeds", on_off(settings.suppress_embeds), true)
+        .field("stats", on_off(settings.show_stats), true)
+        .field("chapters", on_off(settings.show_chapters), true)
+        .field(
+            "no_age_restricted",
+            on_off(settings.skip_age_restricted),
+            true,
+        )
+        .field("live", on_off(settings.live_alerts), true)
+        .field("dedup", on_off(settings.dedup_titles), true)
+        .field("welcome", on_off(settings.awaiting_first), true)
+        .field("thread", thread, true)
+        .field("interval", interval, true)
+        .field("digest", on_off(settings.daily_digest), true)
+        .field(
+            "reactions",
+            if settings.reactions.is_empty() {
+                "none".to_string()
+            } else {
+                settings.reactions.join(" ")
+            },
+            true,
+        )
+        .field(
+            "batch",
+            match settings.batch_window {
+                0 => "off".to_string(),
+                n => format!("{} minutes", n),
+            },
+            true,
+        );
+    edit_deferred_message(&ctx, &command, |r| r.embed(embed)).await
+}
+
+const MAX_REPORT_LENGTH: u16 = 1500;
+// Per user, so that one person can't flood the admins' DMs.
+// Not a RateLimiter, since that makes every caller wait their turn on one shared resource instead of turning them away.
+const REPORT_COOLDOWN: Duration = Duration::from_secs(10 * 60);
+static LAST_REPORT: Mutex<BoundedMap<UserId, Instant>> = Mutex::const_new(BoundedMap::new());
+
+// 0 (the default) sends reports to each admin's DMs instead.
+pub fn report_channel() -> Option<ChannelId> {
+    let id = CONFIG
+        .get()
+        .unwrap()
+        .get_int("report_channel")
+        .expect("Failed to parse report_channel as an integer");
+    (id > 0).then(|| ChannelId::new(id as u64))
+}
+
+async fn report_command(ctx: Context, command: CommandInteraction) -> Result<(), SerenityError> {
+    let message = match find_option(&command.data.options(), "message") {
+        Some(ResolvedValue::String(s)) => s.to_string(),
+        _ => {
+            return send_simple_response_message(&ctx, &command, "Missing required options.", true)
+                .await
+        }
+    };
+
+    let admins = ADMIN_USERS
+        .get()
+        .expect("Admin list somehow uninitialized??");
+    let channel = report_channel();
+    if channel.is_none() && admins.is_empty() {
+        return send_simple_response_message(
+            &ctx,
+            &command,
+            "This bot doesn't have anyone to send reports to.",
+            true,
+        )
+        .await;
+    }
+
+    simple_defer(&ctx, &command, true).await?;
+
+    // Only recorded once the defer went through, so a failed interaction doesn't cost them the cooldown
+    {
+        let mut last_report = LAST_REPORT.lock().await;
+        if let Some(wait) = last_report
+            .get(&command.user.id)
+            .and_then(|last| REPORT_COOLDOWN.checked_sub(last.elapsed()))
+        {
+            return edit_deferred_message_simple(
+                &ctx,
+                &command,
+                format!("You can send another report in {}.", format_duration(wait)),
+            )
+            .await;
+        }
+        last_report.insert(command.user.id, Instant::now());
+    }
+
+    let content = format!(
+        "Report from {} ({}) in {}, channel {}:\n>>> {}",
+        command.user.name,
+        command.user.id.get(),
+        match command.guild_id {
+            Some(g) => format!("server {}", g.get()),
+            None => "DMs".to_string(),
+        },
+        command.channel_id.get(),
+        message
+    );
+
+    // The message is whatever the user typed, so it mustn't be able to ping anyone
+    let report = || {
+        CreateMessage::new()
+            .content(&content)
+            .allowed_mentions(CreateAllowedMentions::new())
+    };
+    // Only fail if nobody got it
+    let mut delivered = false;
+    match channel {
+        Some(channel) => match channel.send_message(&ctx.http, report()).await {
+            Ok(_) => delivered = true,
+            Err(e) => log_error(format!("send_message in report_command:\t{}", e)),
+        },
+        None => {
+            for admin in admins {
+                match admin.direct_message(&ctx.http, report()).await {
+                    Ok(_) => delivered = true,
+                    Err(e) => log_error(format!("direct_message in report_command:\t{}", e)),
+                }
+            }
+        }
+    }
+
+    if delivered {
+        edit_deferred_message_simple(&ctx, &command, "Thanks, your report was sent.").await
+    } else {
+        // Let them try again right away, since it never arrived
+        LAST_REPORT.lock().await.remove(&command.user.id);
+        edit_deferred_message_simple(
+            &ctx,
+            &command,
+            "Sorry, your report couldn't be delivered. Please try again later.",
+        )
+        .await
+    }
+}
+
+async fn resend_command(ctx: Context, command: CommandInteraction) -> Result<(), SerenityError> {
+    simple_defer(&ctx, &command, true).await?;
+
+    let options = command.data.options();
+    let url = match find_option(&options, "channel_url") {
+        Some(url) => url,
+        None => {
+            return edit_deferred_message_simple(&ctx, &command, "Missing required options.").await
+        }
+    };
+    let playlist_id = match get_playlist_id_from_url(url, &ctx, &command).await {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+
+    // Only subscriptions can be resent, so that this can't be used to post arbitrary channels' videos.
+    let settings = match get_subscription_settings(&playlist_id, &command.channel_id).await {
+        Ok(Some((_, settings))) => settings,
+        Ok(None) => {
+            return edit_deferred_message_simple(
                 &ctx,
                 &command,
                 format!(
-                    "Successfully unsubscribed channel {} from uploads playlist {}.",
+                    "Channel {} isn't subscribed to uploads playlist {}.",
                     command.channel_id.get(),
                     playlist_id
                 ),
@@ -300,13 +1184,110 @@ async fn unsubscribe_command(
             .await
         }
         Err(e) => {
+            return edit_deferred_message_simple(
+                &ctx,
+                &command,
+                format!("Failed to read settings from database: {}", e),
+            )
+            .await
+        }
+    };
+
+    match resend_latest(
+        &YoutubeApi,
+        &playlist_id,
+        command.channel_id,
+        settings,
+        &ctx.http,
+    )
+    .await
+    {
+        Ok(video_id) => {
             edit_deferred_message_simple(
                 &ctx,
                 &command,
-                format!("Failed to remove entry to database: {}", e),
+                format!("Sent https://youtu.be/{} again.", video_id),
+            )
+            .await
+        }
+        Err(e) => edit_deferred_message_simple(&ctx, &command, e).await,
+    }
+}
+
+async fn unsubscribe_command(
+    ctx: Context,
+    command: CommandInteraction,
+) -> Result<(), SerenityError> {
+    simple_defer(&ctx, &command, true).await?;
+
+    let options = command.data.options();
+    let playlist_id = match (
+        find_option(&options, "channel_url"),
+        find_option(&options, "index"),
+    ) {
+        (Some(ResolvedValue::String(url)), None) => match resolve_playlist_id(url).await {
+            Ok(s) => s,
+            Err(message) => {
+                let retry = retry_row(format!("retry:unsubscribe:{}", url));
+                return edit_deferred_message(&ctx, &command, |r| {
+                    r.content(message).components(retry.into_iter().collect())
+                })
+                .await;
+            }
+        },
+        (None, Some(ResolvedValue::Integer(index))) => {
+            // Same query as /list, so the numbers line up
+            let playlists = match get_channel_playlists(command.channel_id).await {
+                Ok(v) => v,
+                Err(e) => {
+                    return edit_deferred_message_simple(
+                        &ctx,
+                        &command,
+                        format!("Failed to get subscriptions from database: {}", e),
+                    )
+                    .await
+                }
+            };
+            match usize::try_from(*index - 1)
+                .ok()
+                .and_then(|i| playlists.get(i))
+            {
+                Some(playlist_id) => playlist_id.clone(),
+                None => {
+                    return edit_deferred_message_simple(
+                        &ctx,
+                        &command,
+                        format!(
+                            "There is no subscription number {} in this channel. Check /list for the current numbers.",
+                            index
+                        ),
+                    )
+                    .await
+                }
+            }
+        }
+        _ => {
+            return edit_deferred_message_simple(
+                &ctx,
+                &command,
+                "Please give either a channel url or an index from /list, but not both.",
             )
             .await
         }
+    };
+
+    let content = unsubscribe_channel(&playlist_id, command.channel_id).await;
+    edit_deferred_message_simple(&ctx, &command, content).await
+}
+
+pub async fn unsubscribe_channel(playlist_id: &String, channel_id: ChannelId) -> String {
+    match delete_channel(playlist_id, channel_id).await {
+        Ok(_) => format!(
+            "Successfully unsubscribed channel {} from uploads playlist {}.",
+            channel_id.get(),
+            playlist_id
+        ),
+        Err(e) => format!("Failed to remove entry to database: {}", e),
     }
 }
 
@@ -328,7 +1309,8 @@ fn format_duration(d: Duration) -> String {
     };
     if ns > 0 {
         buffer.push('.');
-        buffer.push_str(ns.to_string().trim_end_matches('0'));
+        // Zero-padded, otherwise 0.05s would come out as 0.5s
+        buffer.push_str(format!("{:09}", ns).trim_end_matches('0'));
     }
     buffer.push('s');
     buffer
@@ -339,14 +1321,34 @@ async fn howmany_command(ctx: Context, command: CommandInteraction) -> Result<()
 
     match get_num_playlists().await {
         Ok(n) => {
-            let full_duration = TIME_PER_REQUEST * n;
+            let full_duration = YOUTUBE.get().unwrap().time_per() * n;
+            let oldest = match get_oldest_last_polled().await {
+                Ok(Some(t)) => format!(
+                    "\nThe least recently checked playlist was checked {} ago.",
+                    format_duration(Duration::from_secs(
+                        (Utc::now() - t).num_seconds().max(0) as u64
+                    ))
+                ),
+                Ok(None) => String::new(),
+                Err(e) => {
+                    log_error(format!("get_oldest_last_polled in howmany_command:\t{}", e));
+                    String::new()
+                }
+            };
+            let paused = if maintenance() {
+                format!("\n{}", maintenance_status())
+            } else {
+                String::new()
+            };
             edit_deferred_message_simple(
                 &ctx,
                 &command,
                 format!(
-                    "Checking {} playlists every {}.",
+                    "Checking {} playlists every {}.{}{}",
                     n,
-                    format_duration(full_duration)
+                    format_duration(full_duration),
+                    oldest,
+                    paused
                 ),
             )
             .await
@@ -361,3 +1363,1230 @@ async fn howmany_command(ctx: Context, command: CommandInteraction) -> Result<()
         }
     }
 }
+
+async fn movehome_command(ctx: Context, command: CommandInteraction) -> Result<(), SerenityError> {
+    let guild_id = match command.guild_id {
+        Some(id) => id,
+        None => {
+            return send_simple_response_message(
+                &ctx,
+                &command,
+                "Home channels can only be set in a server.",
+                true,
+            )
+            .await
+        }
+    };
+
+    let options = command.data.options();
+    let new_home = match (
+        find_option(&options, "channel"),
+        find_option(&options, "clear"),
+    ) {
+        (Some(ResolvedValue::Channel(channel)), _) => Some(Some(channel.id)),
+        (None, Some(ResolvedValue::Boolean(true))) => Some(None),
+        _ => None,
+    };
+
+    let home = match new_home {
+        Some(home) => {
+            if let Err(e) = set_guild_default_channel(guild_id, home).await {
+                return send_simple_response_message(
+                    &ctx,
+                    &command,
+                    format!("Failed to update database: {}", e),
+                    true,
+                )
+                .await;
+            }
+            home
+        }
+        None => match get_guild_default_channel(guild_id).await {
+            Ok(home) => home,
+            Err(e) => {
+                return send_simple_response_message(
+                    &ctx,
+                    &command,
+                    format!("Failed to read home channel from database: {}", e),
+                    true,
+                )
+                .await
+            }
+        },
+    };
+
+    send_simple_response_message(
+        &ctx,
+        &command,
+        match home {
+            Some(channel_id) => format!(
+                "New subscriptions in this server go to {}, unless /subscribe is used with `here: True`.",
+                channel_id.mention()
+            ),
+            None => "New subscriptions in this server go to whichever channel /subscribe is used in."
+                .to_string(),
+        },
+        true,
+    )
+    .await
+}
+
+async fn timezone_command(ctx: Context, command: CommandInteraction) -> Result<(), SerenityError> {
+    let guild_id = match command.guild_id {
+        Some(id) => id,
+        None => {
+            return send_simple_response_message(
+                &ctx,
+                &command,
+                "Timezones can only be set in a server.",
+                true,
+            )
+            .await
+        }
+    };
+
+    let timezone = match command.data.options().first().map(|o| &o.value) {
+        Some(ResolvedValue::String(s)) => {
+            // Validate before storing, so that everything reading it back can assume it parses.
+            let tz = match s.parse::<Tz>() {
+                Ok(tz) => tz,
+                Err(_) => {
+                    return send_simple_response_message(
+                        &ctx,
+                        &command,
+                        format!(
+                            "Unknown timezone \"{}\". Please use an IANA timezone name, such as `America/New_York`.",
+                            s
+                        ),
+                        true,
+                    )
+                    .await
+                }
+            };
+            if let Err(e) = set_guild_timezone(guild_id, tz.name()).await {
+                return send_simple_response_message(
+                    &ctx,
+                    &command,
+                    format!("Failed to update database: {}", e),
+                    true,
+                )
+                .await;
+            }
+            tz
+        }
+        _ => match get_guild_timezone(guild_id).await {
+            Ok(name) => name.and_then(|n| n.parse().ok()).unwrap_or(Tz::UTC),
+            Err(e) => {
+                return send_simple_response_message(
+                    &ctx,
+                    &command,
+                    format!("Failed to read timezone from database: {}", e),
+                    true,
+                )
+                .await
+            }
+        },
+    };
+
+    send_simple_response_message(
+        &ctx,
+        &command,
+        format!(
+            "This server's timezone is {} (currently {}).",
+            timezone.name(),
+            Utc::now().with_timezone(&timezone).format("%Y-%m-%d %H:%M")
+        ),
+        true,
+    )
+    .await
+}
+
+// The timezone set with /timezone, falling back to UTC outside of servers or if it can't be read.
+async fn display_timezone(guild_id: Option<GuildId>) -> Tz {
+    let guild_id = match guild_id {
+        Some(id) => id,
+        None => return Tz::UTC,
+    };
+    match get_guild_timezone(guild_id).await {
+        Ok(name) => name.and_then(|n| n.parse().ok()).unwrap_or(Tz::UTC),
+        Err(e) => {
+            log_error(format!("get_guild_timezone in display_timezone:\t{}", e));
+            Tz::UTC
+        }
+    }
+}
+
+// Quote any field that would otherwise break the CSV structure, doubling any quotes inside it.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+async fn export_csv_command(
+    ctx: Context,
+    command: CommandInteraction,
+) -> Result<(), SerenityError> {
+    if !is_admin(&command) {
+        return send_simple_response_message(&ctx, &command, "You do not have permission.", true)
+            .await;
+    }
+    let guild_id = match command.guild_id {
+        Some(id) => id,
+        None => {
+            return send_simple_response_message(
+                &ctx,
+                &command,
+                "Subscriptions can only be exported from a server.",
+                true,
+            )
+            .await
+        }
+    };
+
+    simple_defer(&ctx, &command, true).await?;
+
+    let guild_channels = match guild_id.channels(&ctx.http).await {
+        Ok(c) => c,
+        Err(e) => {
+            return edit_deferred_message_simple(
+                &ctx,
+                &command,
+                format!("Failed to get this server's channels: {}", e),
+            )
+            .await
+        }
+    };
+
+    let subscriptions = match get_subscriptions().await {
+        Ok(v) => v,
+        Err(e) => {
+            return edit_deferred_message_simple(
+                &ctx,
+                &command,
+                format!("Failed to get subscriptions from database: {}", e),
+            )
+            .await
+        }
+    };
+
+    let mut count = 0;
+    let mut csv = String::from(
+        "playlist_id,channel_title,discord_channel_id,unlisted_allowed,suppress_embeds,show_stats,\
+        skip_age_restricted,live_alerts,dedup_titles,use_thread,show_chapters,daily_digest,\
+        notify_interval,batch_window,reactions,most_recent\n",
+    );
+    // Each creator is looked up once, however many channels here are subscribed to them.
+    // This costs API quota, so a failed lookup just leaves the title empty.
+    let mut channel_titles: BTreeMap<&String, String> = BTreeMap::new();
+    // Collected first, since holding the filter across an await makes this future not Send
+    let subscriptions: Vec<&Subscription> = subscriptions
+        .iter()
+        .filter(|s| guild_channels.contains_key(&s.channel_id))
+        .collect();
+    for sub in subscriptions {
+        if !channel_titles.contains_key(&sub.playlist_id) {
+            let title = match get_playlist_preview(&sub.playlist_id).await {
+                Ok(preview) => preview.channel_title.unwrap_or_default(),
+                Err(e) => {
+                    log_error(format!(
+                        "get_playlist_preview in export_csv_command:\t{}",
+                        e
+                    ));
+                    String::new()
+                }
+            };
+            channel_titles.insert(&sub.playlist_id, title);
+        }
+        let s = &sub.settings;
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+            csv_field(&sub.playlist_id),
+            csv_field(&channel_titles[&sub.playlist_id]),
+            sub.channel_id.get(),
+            s.unlisted_allowed,
+            s.suppress_embeds,
+            s.show_stats,
+            s.skip_age_restricted,
+            s.live_alerts,
+            s.dedup_titles,
+            s.use_thread,
+            s.show_chapters,
+            s.daily_digest,
+            s.notify_interval,
+            s.batch_window,
+            csv_field(&s.reactions.join(" ")),
+            csv_field(&sub.most_recent.to_rfc3339())
+        ));
+        count += 1;
+    }
+
+    edit_deferred_message(&ctx, &command, |r| {
+        r.content(format!("Exported {} subscriptions.", count))
+            .new_attachment(CreateAttachment::bytes(csv, "subscriptions.csv"))
+    })
+    .await
+}
+
+async fn poll_command(ctx: Context, command: CommandInteraction) -> Result<(), SerenityError> {
+    if !is_admin(&command) {
+        return send_simple_response_message(&ctx, &command, "You do not have permission.", true)
+            .await;
+    }
+
+    // Polling now would send everything that's being held
+    if maintenance_hold() {
+        return send_simple_response_message(&ctx, &command, maintenance_status(), true).await;
+    }
+
+    simple_defer(&ctx, &command, true).await?;
+
+    let playlist_id =
+        match get_playlist_id_from_url(&command.data.options()[0].value, &ctx, &command).await {
+            Ok(s) => s,
+            Err(e) => return e,
+        };
+
+    // Any errors along the way only show up in the logs, same as in the update loop.
+    let sent = process_single_playlist(&YoutubeApi, &playlist_id, &ctx.http).await;
+    edit_deferred_message_simple(
+        &ctx,
+        &command,
+        format!(
+            "Polled uploads playlist {}, and sent {} notifications across all subscribed channels.",
+            playlist_id, sent
+        ),
+    )
+    .await
+}
+
+async fn failures_command(ctx: Context, command: CommandInteraction) -> Result<(), SerenityError> {
+    if !is_admin(&command) {
+        return send_simple_response_message(&ctx, &command, "You do not have permission.", true)
+            .await;
+    }
+
+    let mut failures = get_failures().await;
+    if failures.is_empty() {
+        return send_simple_response_message(
+            &ctx,
+            &command,
+            "Every playlist's most recent poll succeeded.",
+            true,
+        )
+        .await;
+    }
+    // Most broken first
+    failures.sort_by_key(|f| std::cmp::Reverse(f.2));
+
+    let mut content = String::new();
+    for (i, (playlist_id, error, count)) in failures.iter().enumerate() {
+        let line = format!(
+            "`{}` failed {} time{} in a row: {}\n",
+            playlist_id,
+            count,
+            if *count == 1 { "" } else { "s" },
+            error.chars().take(200).collect::<String>()
+        );
+        // Leave room for the note about the rest
+        if content.len() + line.len() > MAX_MESSAGE_LENGTH - 50 {
+            content.push_str(&format!("...and {} more.", failures.len() - i));
+            break;
+        }
+        content.push_str(&line);
+    }
+    send_simple_response_message(&ctx, &command, content, true).await
+}
+
+fn maintenance_status() -> String {
+    if !maintenance() {
+        "Notifications are being sent normally.".to_string()
+    } else if maintenance_mode() == "hold" {
+        "Maintenance mode is on: playlists aren't being checked, and new videos will be sent once it's turned off."
+            .to_string()
+    } else {
+        "Maintenance mode is on: new videos are being marked as seen without being sent."
+            .to_string()
+    }
+}
+
+async fn maintenance_command(
+    ctx: Context,
+    command: CommandInteraction,
+) -> Result<(), SerenityError> {
+    if !is_admin(&command) {
+        return send_simple_response_message(&ctx, &command, "You do not have permission.", true)
+            .await;
+    }
+    if let Some(ResolvedValue::Boolean(enabled)) = find_option(&command.data.options(), "enabled") {
+        set_maintenance(*enabled);
+        log_info(format!(
+            "Maintenance mode turned {} by user {} with Id {}",
+            if *enabled { "on" } else { "off" },
+            command.user.name,
+            command.user.id
+        ));
+    }
+    send_simple_response_message(&ctx, &command, maintenance_status(), true).await
+}
+
+async fn setpollrate_command(
+    ctx: Context,
+    command: CommandInteraction,
+) -> Result<(), SerenityError> {
+    if !is_admin(&command) {
+        return send_simple_response_message(&ctx, &command, "You do not have permission.", true)
+            .await;
+    }
+    let millis = match command.data.options()[0].value {
+        ResolvedValue::Integer(i) => i,
+        _ => unreachable!(),
+    };
+    // Discord enforces min_int_value, but the daily quota is important enough to double check.
+    if millis < TIME_PER_REQUEST.as_millis() as i64 {
+        return send_simple_response_message(
+            &ctx,
+            &command,
+            format!(
+                "That would exceed the YouTube API's daily quota. The minimum is {} ms.",
+                TIME_PER_REQUEST.as_millis()
+            ),
+            true,
+        )
+        .await;
+    }
+
+    simple_defer(&ctx, &command, true).await?;
+
+    let time_per = Duration::from_millis(millis as u64);
+    YOUTUBE.get().unwrap().set_time_per(time_per);
+    log_info(format!(
+        "Poll rate set to {} ms by user {} with Id {}",
+        millis, command.user.name, command.user.id
+    ));
+
+    let content = match get_num_playlists().await {
+        Ok(n) => format!(
+            "Now waiting {} ms between requests, so checking {} playlists every {}.",
+            millis,
+            n,
+            format_duration(time_per * n)
+        ),
+        Err(e) => {
+            log_error(format!("get_num_playlists in setpollrate_command:\t{}", e));
+            format!("Now waiting {} ms between requests.", millis)
+        }
+    };
+    edit_deferred_message_simple(&ctx, &command, content).await
+}
+
+async fn resolve_command(ctx: Context, command: CommandInteraction) -> Result<(), SerenityError> {
+    if !is_admin(&command) {
+        return send_simple_response_message(&ctx, &command, "You do not have permission.", true)
+            .await;
+    }
+
+    let url = match find_option(&command.data.options(), "channel_url") {
+        Some(ResolvedValue::String(s)) => s.to_string(),
+        _ => {
+            return send_simple_response_message(&ctx, &command, "Missing channel url.", true).await
+        }
+    };
+
+    simple_defer(&ctx, &command, true).await?;
+
+    let trace = trace_upload_playlist_id(&url).await;
+
+    let mut content = format!("Input: `{}`\n", url);
+    content.push_str(&match &trace.normalized {
+        Ok(u) => format!("Normalized: <{}>\n", u),
+        Err(e) => format!("Normalized: failed, {:?}\n", e),
+    });
+    content.push_str(&format!(
+        "Channel page: {}\n",
+        trace
+            .channel_path
+            .as_deref()
+            .unwrap_or("(not a channel url)")
+    ));
+    content.push_str(&format!(
+        "From /channel/ url: {}\n",
+        trace.fast_path.as_deref().unwrap_or("(not applicable)")
+    ));
+    content.push_str(&format!(
+        "From API: {}\n",
+        match &trace.api {
+            None => "(playlist_id_strategy isn't \"api\")",
+            Some(None) => "(nothing found)",
+            Some(Some(id)) => id,
+        }
+    ));
+    content.push_str(&match &trace.scrape {
+        None => "From scrape: (not attempted)\n".to_string(),
+        Some(Ok(id)) => format!("From scrape: {}\n", id),
+        Some(Err(e)) => format!("From scrape: failed, {:?}\n", e),
+    });
+
+    // Same order of preference as get_upload_playlist_id
+    let result = trace
+        .fast_path
+        .clone()
+        .or(trace.api.flatten())
+        .or(trace.scrape.and_then(|r| r.ok()));
+    content.push_str(&format!(
+        "**Result: {}**",
+        result.as_deref().unwrap_or("(none)")
+    ));
+
+    edit_deferred_message_simple(&ctx, &command, content).await
+}
+
+async fn inspect_command(ctx: Context, command: CommandInteraction) -> Result<(), SerenityError> {
+    let video_id = match find_option(&command.data.options(), "video") {
+        Some(ResolvedValue::String(s)) => parse_video_id(s),
+        _ => None,
+    };
+    let video_id = match video_id {
+        Some(id) => id,
+        None => {
+            return send_simple_response_message(
+                &ctx,
+                &command,
+                "That doesn't look like a YouTube video url or id.",
+                true,
+            )
+            .await
+        }
+    };
+
+    simple_defer(&ctx, &command, true).await?;
+
+    let extras = match get_extras_by_id(&[video_id.as_str()]).await {
+        Ok(mut v) if v.len() == 1 => v.remove(0),
+        // YouTube leaves out videos that don't exist (or that it won't tell us about)
+        Ok(_) | Err(ExtrasError::NoItemsReturned(_)) | Err(ExtrasError::LengthMismatch(_)) => {
+            return edit_deferred_message_simple(
+                &ctx,
+                &command,
+                format!("Couldn't find a video with id {}.", video_id),
+            )
+            .await
+        }
+        Err(e) => {
+            log_error(format!("get_extras_by_id in inspect_command:\t{:?}", e));
+            return edit_deferred_message_simple(
+                &ctx,
+                &command,
+                "Failed to get the video's details from YouTube.",
+            )
+            .await;
+        }
+    };
+
+    // Keep this in sync with announce_allowed in update_loop.rs
+    let verdict = match (extras.privacy_status.as_str(), extras.age_restricted) {
+        ("public", false) => "Would be announced to every subscription.",
+        ("public", true) => "Would be announced to every subscription without `/config no_age_restricted` turned on.",
+        ("unlisted", false) => "Would only be announced to subscriptions with `/config unlisted` turned on.",
+        ("unlisted", true) => "Would only be announced to subscriptions with `/config unlisted` turned on, and `/config no_age_restricted` turned off.",
+        _ => "Would never be announced.",
+    };
+    edit_deferred_message_simple(
+        &ctx,
+        &command,
+        format!(
+            "**{}** (https://youtu.be/{})\nDuration: `{}`\nPrivacy: {}\nAge restricted: {}\n{}",
+            extras.title.as_deref().unwrap_or("Untitled"),
+            video_id,
+            extras.duration,
+            extras.privacy_status,
+            if extras.age_restricted { "yes" } else { "no" },
+            verdict
+        ),
+    )
+    .await
+}
+
+fn find_option<'a>(options: &'a [ResolvedOption<'a>], name: &str) -> Option<&'a ResolvedValue<'a>> {
+    options.iter().find(|o| o.name == name).map(|o| &o.value)
+}
+
+async fn config_command(ctx: Context, command: CommandInteraction) -> Result<(), SerenityError> {
+    let options = command.data.options();
+    // Add any /config subcommands here, as well as in create_commands
+    match options.first() {
+        Some(ResolvedOption {
+            name,
+            value: ResolvedValue::SubCommand(sub_options),
+            ..
+        }) => match *name {
+            "unlisted" => {
+                config_bool_setting(
+                    &ctx,
+                    &command,
+                    sub_options,
+                    "unlisted_allowed",
+                    "Announcing unlisted videos",
+                )
+                .await
+            }
+            "no_embeds" => {
+                config_bool_setting(
+                    &ctx,
+                    &command,
+                    sub_options,
+                    "suppress_embeds",
+                    "Suppressing video previews",
+                )
+                .await
+            }
+            "welcome" => {
+                config_bool_setting(
+                    &ctx,
+                    &command,
+                    sub_options,
+                    "awaiting_first",
+                    "Welcome line on the next notification",
+                )
+                .await
+            }
+            "stats" => {
+                config_bool_setting(
+                    &ctx,
+                    &command,
+                    sub_options,
+                    "show_stats",
+                    "Showing view and like counts",
+                )
+                .await
+            }
+            "interval" => config_interval_setting(&ctx, &command, sub_options).await,
+            "batch" => config_batch_setting(&ctx, &command, sub_options).await,
+            "reactions" => config_reactions_setting(&ctx, &command, sub_options).await,
+            "no_age_restricted" => {
+                config_bool_setting(
+                    &ctx,
+                    &command,
+                    sub_options,
+                    "skip_age_restricted",
+                    "Skipping age-restricted videos",
+                )
+                .await
+            }
+            "live" => {
+                config_bool_setting(
+                    &ctx,
+                    &command,
+                    sub_options,
+                    "live_alerts",
+                    "Alerting when a stream goes live",
+                )
+                .await
+            }
+            "dedup" => {
+                config_bool_setting(
+                    &ctx,
+                    &command,
+                    sub_options,
+                    "dedup_titles",
+                    "Skipping repeated titles",
+                )
+                .await
+            }
+            "thread" => {
+                config_bool_setting(
+                    &ctx,
+                    &command,
+                    sub_options,
+                    "use_thread",
+                    "Posting in a thread for this creator",
+                )
+                .await
+            }
+            "chapters" => {
+                config_bool_setting(
+                    &ctx,
+                    &command,
+                    sub_options,
+                    "show_chapters",
+                    "Listing chapters",
+                )
+                .await
+            }
+            "digest" => {
+                config_bool_setting(
+                    &ctx,
+                    &command,
+                    sub_options,
+                    "daily_digest",
+                    "Sending a daily digest",
+                )
+                .await
+            }
+            _ => {
+                send_simple_response_message(
+                    &ctx,
+                    &command,
+                    "This setting hasn't been implemented. Try /help",
+                    true,
+                )
+                .await
+            }
+        },
+        _ => {
+            send_simple_response_message(&ctx, &command, "Please choose a setting to change.", true)
+                .await
+        }
+    }
+}
+
+async fn setfilters_all_command(
+    ctx: Context,
+    command: CommandInteraction,
+) -> Result<(), SerenityError> {
+    simple_defer(&ctx, &command, true).await?;
+
+    let options = command.data.options();
+    let (setting, enabled) = match (
+        find_option(&options, "setting"),
+        find_option(&options, "enabled"),
+    ) {
+        (Some(ResolvedValue::String(s)), Some(ResolvedValue::Boolean(b)))
+            if BOOL_SETTINGS.contains(s) =>
+        {
+            (*s, *b)
+        }
+        _ => {
+            return edit_deferred_message_simple(&ctx, &command, "Missing required options.").await
+        }
+    };
+
+    let count = match get_channel_playlists(command.channel_id).await {
+        Ok(v) => v.len(),
+        Err(e) => {
+            return edit_deferred_message_simple(
+                &ctx,
+                &command,
+                format!("Failed to get subscriptions from database: {}", e),
+            )
+            .await
+        }
+    };
+    if count == 0 {
+        return edit_deferred_message_simple(
+            &ctx,
+            &command,
+            "This channel isn't subscribed to anything.",
+        )
+        .await;
+    }
+
+    // Changing everything at once is easy to regret, so make them confirm it first.
+    edit_deferred_message(&ctx, &command, |r| {
+        r.content(format!(
+            "Turn `{}` {} for all {} subscriptions in this channel?",
+            setting,
+            if enabled { "on" } else { "off" },
+            count
+        ))
+        .components(vec![CreateActionRow::Buttons(vec![make_button(
+            format!("setall:{}:{}", setting, enabled),
+            ButtonStyle::Danger,
+            Some('✅'),
+            Some("Confirm"),
+            false,
+        )])])
+    })
+    .await
+}
+
+async fn config_interval_setting(
+    ctx: &Context,
+    command: &CommandInteraction,
+    options: &[ResolvedOption<'_>],
+) -> Result<(), SerenityError> {
+    simple_defer(ctx, command, true).await?;
+
+    let (url, minutes) = match (
+        find_option(options, "channel_url"),
+        find_option(options, "minutes"),
+    ) {
+        (Some(url), Some(ResolvedValue::Integer(m))) => {
+            (url, (*m).clamp(0, MAX_NOTIFY_INTERVAL_MINUTES as i64))
+        }
+        _ => return edit_deferred_message_simple(ctx, command, "Missing required options.").await,
+    };
+
+    let playlist_id = match get_playlist_id_from_url(url, ctx, command).await {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+
+    let content = if minutes == 0 {
+        format!(
+            "Every video from uploads playlist {} will be sent right away in channel {}.",
+            playlist_id,
+            command.channel_id.get()
+        )
+    } else {
+        format!(
+            "Uploads playlist {} will send at most one message every {} in channel {}.",
+            playlist_id,
+            format_duration(Duration::from_secs(minutes as u64 * 60)),
+            command.channel_id.get()
+        )
+    };
+
+    match set_notify_interval(&playlist_id, command.channel_id, minutes).await {
+        Ok(r) if r.rows_affected() == 0 => {
+            edit_deferred_message_simple(
+                ctx,
+                command,
+                format!(
+                    "Channel {} isn't subscribed to uploads playlist {}.",
+                    command.channel_id.get(),
+                    playlist_id
+                ),
+            )
+            .await
+        }
+        Ok(_) => edit_deferred_message_simple(ctx, command, content).await,
+        Err(e) => {
+            edit_deferred_message_simple(ctx, command, format!("Failed to update database: {}", e))
+                .await
+        }
+    }
+}
+
+async fn config_batch_setting(
+    ctx: &Context,
+    command: &CommandInteraction,
+    options: &[ResolvedOption<'_>],
+) -> Result<(), SerenityError> {
+    simple_defer(ctx, command, true).await?;
+
+    let (url, minutes) = match (
+        find_option(options, "channel_url"),
+        find_option(options, "minutes"),
+    ) {
+        (Some(url), Some(ResolvedValue::Integer(m))) => {
+            (url, (*m).clamp(0, MAX_BATCH_WINDOW_MINUTES as i64))
+        }
+        _ => return edit_deferred_message_simple(ctx, command, "Missing required options.").await,
+    };
+
+    let playlist_id = match get_playlist_id_from_url(url, ctx, command).await {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+
+    let content = if minutes == 0 {
+        format!(
+            "Every video from uploads playlist {} will be sent right away in channel {}.",
+            playlist_id,
+            command.channel_id.get()
+        )
+    } else {
+        format!(
+            "Uploads playlist {} will wait {} after each new video in channel {}, and send everything that came out in that time together.",
+            playlist_id,
+            format_duration(Duration::from_secs(minutes as u64 * 60)),
+            command.channel_id.get()
+        )
+    };
+
+    match set_batch_window(&playlist_id, command.channel_id, minutes).await {
+        Ok(r) if r.rows_affected() == 0 => {
+            edit_deferred_message_simple(
+                ctx,
+                command,
+                format!(
+                    "Channel {} isn't subscribed to uploads playlist {}.",
+                    command.channel_id.get(),
+                    playlist_id
+                ),
+            )
+            .await
+        }
+        Ok(_) => edit_deferred_message_simple(ctx, command, content).await,
+        Err(e) => {
+            edit_deferred_message_simple(ctx, command, format!("Failed to update database: {}", e))
+                .await
+        }
+    }
+}
+
+async fn config_reactions_setting(
+    ctx: &Context,
+    command: &CommandInteraction,
+    options: &[ResolvedOption<'_>],
+) -> Result<(), SerenityError> {
+    simple_defer(ctx, command, true).await?;
+
+    let url = match find_option(options, "channel_url") {
+        Some(url) => url,
+        None => {
+            return edit_deferred_message_simple(ctx, command, "Missing required options.").await
+        }
+    };
+    let reactions: Vec<&str> = match find_option(options, "emojis") {
+        Some(ResolvedValue::String(s)) => s.split_whitespace().collect(),
+        _ => vec![],
+    };
+    if reactions.len() > MAX_REACTIONS {
+        return edit_deferred_message_simple(
+            ctx,
+            command,
+            format!("Please choose at most {} emojis.", MAX_REACTIONS),
+        )
+        .await;
+    }
+    // Whether the bot can actually use them is only known once it tries, so this just checks the format.
+    if let Some(bad) = reactions
+        .iter()
+        .find(|r| ReactionType::try_from(**r).is_err())
+    {
+        return edit_deferred_message_simple(
+            ctx,
+            command,
+            format!("\"{}\" doesn't look like an emoji.", bad),
+        )
+        .await;
+    }
+
+    let playlist_id = match get_playlist_id_from_url(url, ctx, command).await {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+
+    let content = if reactions.is_empty() {
+        format!(
+            "Notifications for uploads playlist {} in channel {} won't get any reactions.",
+            playlist_id,
+            command.channel_id.get()
+        )
+    } else {
+        format!(
+            "Notifications for uploads playlist {} in channel {} will get these reactions: {}",
+            playlist_id,
+            command.channel_id.get(),
+            reactions.join(" ")
+        )
+    };
+
+    match set_reactions(&playlist_id, command.channel_id, &reactions.join(" ")).await {
+        Ok(r) if r.rows_affected() == 0 => {
+            edit_deferred_message_simple(
+                ctx,
+                command,
+                format!(
+                    "Channel {} isn't subscribed to uploads playlist {}.",
+                    command.channel_id.get(),
+                    playlist_id
+                ),
+            )
+            .await
+        }
+        Ok(_) => edit_deferred_message_simple(ctx, command, content).await,
+        Err(e) => {
+            edit_deferred_message_simple(ctx, command, format!("Failed to update database: {}", e))
+                .await
+        }
+    }
+}
+
+async fn config_bool_setting(
+    ctx: &Context,
+    command: &CommandInteraction,
+    options: &[ResolvedOption<'_>],
+    setting: &str,
+    description: &str,
+) -> Result<(), SerenityError> {
+    simple_defer(ctx, command, true).await?;
+
+    let (url, enabled) = match (
+        find_option(options, "channel_url"),
+        find_option(options, "enabled"),
+    ) {
+        (Some(url), Some(ResolvedValue::Boolean(b))) => (url, *b),
+        _ => return edit_deferred_message_simple(ctx, command, "Missing required options.").await,
+    };
+
+    let playlist_id = match get_playlist_id_from_url(url, ctx, command).await {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+
+    match set_subscription_setting(&playlist_id, command.channel_id, setting, enabled).await {
+        Ok(r) if r.rows_affected() == 0 => {
+            edit_deferred_message_simple(
+                ctx,
+                command,
+                format!(
+                    "Channel {} isn't subscribed to uploads playlist {}.",
+                    command.channel_id.get(),
+                    playlist_id
+                ),
+            )
+            .await
+        }
+        Ok(_) => {
+            edit_deferred_message_simple(
+                ctx,
+                command,
+                format!(
+                    "{}: {} for uploads playlist {} in channel {}.",
+                    description,
+                    if enabled { "on" } else { "off" },
+                    playlist_id,
+                    command.channel_id.get()
+                ),
+            )
+            .await
+        }
+        Err(e) => {
+            edit_deferred_message_simple(ctx, command, format!("Failed to update database: {}", e))
+                .await
+        }
+    }
+}
+
+async fn resetcursors_command(
+    ctx: Context,
+    command: CommandInteraction,
+) -> Result<(), SerenityError> {
+    if !is_admin(&command) {
+        return send_simple_response_message(&ctx, &command, "You do not have permission.", true)
+            .await;
+    }
+
+    simple_defer(&ctx, &command, true).await?;
+
+    let count = match get_channel_playlists(command.channel_id).await {
+        Ok(v) => v.len(),
+        Err(e) => {
+            return edit_deferred_message_simple(
+                &ctx,
+                &command,
+                format!("Failed to get subscriptions from database: {}", e),
+            )
+            .await
+        }
+    };
+    if count == 0 {
+        return edit_deferred_message_simple(
+            &ctx,
+            &command,
+            "This channel isn't subscribed to anything.",
+        )
+        .await;
+    }
+
+    edit_deferred_message(&ctx, &command, |r| {
+        r.content(format!(
+            "Reset the cursors of all {} subscriptions in this channel to now? Anything not yet sent will be skipped.",
+            count
+        ))
+        .components(vec![CreateActionRow::Buttons(vec![make_button(
+            "resetcursors",
+            ButtonStyle::Danger,
+            Some('✅'),
+            Some("Confirm"),
+            false,
+        )])])
+    })
+    .await
+}
+
+async fn setcursor_command(ctx: Context, command: CommandInteraction) -> Result<(), SerenityError> {
+    if !is_admin(&command) {
+        return send_simple_response_message(&ctx, &command, "You do not have permission.", true)
+            .await;
+    }
+
+    let options = command.data.options();
+    let timestamp = match find_option(&options, "timestamp") {
+        Some(ResolvedValue::String(s)) if s.eq_ignore_ascii_case("now") => Utc::now(),
+        Some(ResolvedValue::String(s)) => match DateTime::parse_from_rfc3339(s) {
+            Ok(dt) => dt.into(),
+            Err(e) => {
+                return send_simple_response_message(
+                    &ctx,
+                    &command,
+                    format!(
+                        "Could not parse \"{}\" as a timestamp ({}). Try something like `2024-08-20T12:00:00Z`, or `now`.",
+                        s, e
+                    ),
+                    true,
+                )
+                .await
+            }
+        },
+        _ => {
+            return send_simple_response_message(
+                &ctx,
+                &command,
+                "Missing required options.",
+                true,
+            )
+            .await
+        }
+    };
+
+    simple_defer(&ctx, &command, true).await?;
+
+    let url = match find_option(&options, "channel_url") {
+        Some(url) => url,
+        None => {
+            return edit_deferred_message_simple(&ctx, &command, "Missing required options.").await
+        }
+    };
+    let playlist_id = match get_playlist_id_from_url(url, &ctx, &command).await {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+
+    match set_most_recent(&playlist_id, &command.channel_id, &timestamp).await {
+        Ok(r) if r.rows_affected() == 0 => {
+            edit_deferred_message_simple(
+                &ctx,
+                &command,
+                format!(
+                    "Channel {} isn't subscribed to uploads playlist {}.",
+                    command.channel_id.get(),
+                    playlist_id
+                ),
+            )
+            .await
+        }
+        Ok(_) => {
+            let timezone = display_timezone(command.guild_id).await;
+            edit_deferred_message_simple(
+                &ctx,
+                &command,
+                format!(
+                    "Set the cursor for uploads playlist {} in channel {} to {} ({}).\nAny videos uploaded after that will be sent (again) on the next check.",
+                    playlist_id,
+                    command.channel_id.get(),
+                    timestamp.with_timezone(&timezone).format("%Y-%m-%d %H:%M:%S"),
+                    timezone.name()
+                ),
+            )
+            .await
+        }
+        Err(e) => {
+            edit_deferred_message_simple(
+                &ctx,
+                &command,
+                format!("Failed to update database: {}", e),
+            )
+            .await
+        }
+    }
+}
+
+async fn watch_command(ctx: Context, command: CommandInteraction) -> Result<(), SerenityError> {
+    simple_defer(&ctx, &command, true).await?;
+
+    let options = command.data.options();
+    let (url, minutes) = match (
+        find_option(&options, "channel_url"),
+        find_option(&options, "minutes"),
+    ) {
+        (Some(url), Some(ResolvedValue::Integer(m))) => {
+            (url, (*m).clamp(1, MAX_WATCH_MINUTES as i64))
+        }
+        _ => {
+            return edit_deferred_message_simple(&ctx, &command, "Missing required options.").await
+        }
+    };
+
+    let playlist_id = match get_playlist_id_from_url(url, &ctx, &command).await {
+        Ok(s) => s,
+        Err(e) => return e,
+    };
+
+    // Watching a playlist nobody is subscribed to would spend quota without ever sending anything
+    match get_playlists(false).await {
+        Ok(playlists) if !playlists.contains(&playlist_id) => {
+            return edit_deferred_message_simple(
+                &ctx,
+                &command,
+                format!(
+                    "Nobody is subscribed to uploads playlist {}, so there's nothing to watch for.",
+                    playlist_id
+                ),
+            )
+            .await
+        }
+        Ok(_) => (),
+        Err(e) => {
+            return edit_deferred_message_simple(
+                &ctx,
+                &command,
+                format!("Failed to get subscriptions from database: {}", e),
+            )
+            .await
+        }
+    }
+
+    boost_playlist(
+        playlist_id.clone(),
+        Duration::from_secs(minutes as u64 * 60),
+    )
+    .await;
+    edit_deferred_message_simple(
+        &ctx,
+        &command,
+        format!(
+            "Checking uploads playlist {} as often as possible for the next {} minutes.",
+            playlist_id, minutes
+        ),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_field_quotes_only_when_needed() {
+        assert_eq!(csv_field("UUabc"), "UUabc");
+        assert_eq!(csv_field("Tom, Dick & Harry"), "\"Tom, Dick & Harry\"");
+        assert_eq!(
+            csv_field("The \"Best\" Channel"),
+            "\"The \"\"Best\"\" Channel\""
+        );
+        assert_eq!(csv_field("line one\nline two"), "\"line one\nline two\"");
+        assert_eq!(csv_field("carriage\rreturn"), "\"carriage\rreturn\"");
+    }
+
+    #[test]
+    fn format_duration_sub_minute() {
+        assert_eq!(format_duration(Duration::ZERO), "0s");
+        assert_eq!(format_duration(Duration::from_secs(5)), "5s");
+        assert_eq!(format_duration(Duration::from_millis(50)), "0.05s");
+    }
+
+    #[test]
+    fn format_duration_pads_smaller_units() {
+        assert_eq!(format_duration(Duration::from_millis(65_500)), "1m 05.5s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h 02m 03s");
+        assert_eq!(format_duration(Duration::from_secs(3600)), "1h 00m 00s");
+    }
+
+    #[test]
+    fn format_duration_multi_hour_and_day() {
+        assert_eq!(
+            format_duration(Duration::from_secs(13 * 3600 + 59)),
+            "13h 00m 59s"
+        );
+        assert_eq!(
+            format_duration(Duration::from_secs(2 * 86400 + 3 * 3600 + 4 * 60 + 5)),
+            "2d 03h 04m 05s"
+        );
+    }
+}
@@ -1,18 +1,74 @@
-use std::time::Instant;
+use crate::commands::{
+    guild_limit_reached, is_admin_user, resolve_playlist_id, subscribe_prompt, unsubscribe_channel,
+    MANAGE_PERMISSIONS,
+};
+use crate::db::{add_channel, reset_channel_cursors, set_channel_setting, BOOL_SETTINGS};
+use crate::youtube::parse_playlist_id;
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
 
 use serenity::all::{
-    ComponentInteraction, Context, CreateInteractionResponse, CreateInteractionResponseMessage,
-    EditInteractionResponse,
+    ChannelId, ComponentInteraction, Context, CreateInteractionResponse,
+    CreateInteractionResponseMessage, EditInteractionResponse, MessageId, UserId,
 };
 use serenity::prelude::SerenityError;
+use tokio::sync::Mutex;
+
+const REFRESH_COOLDOWN: Duration = Duration::from_secs(3);
+
+// When each user last refreshed each ping message, so the refresh button can't be spammed.
+static LAST_REFRESH: Mutex<BTreeMap<(MessageId, UserId), Instant>> =
+    Mutex::const_new(BTreeMap::new());
 
 pub async fn handle_component(
     ctx: Context,
     component: ComponentInteraction,
 ) -> Result<(), SerenityError> {
+    // Buttons that carry data encode it after a colon in their custom_id
+    if let Some(data) = component.data.custom_id.strip_prefix("subscribe:") {
+        // Older buttons only have the playlist id, and always meant the channel they were in
+        let (playlist_id, mut channel_ids) = match data.split_once(':') {
+            Some((playlist_id, channel_ids)) => (
+                playlist_id.to_string(),
+                channel_ids
+                    .split(',')
+                    .filter_map(|c| c.parse().ok().map(ChannelId::new))
+                    .collect::<Vec<_>>(),
+            ),
+            None => (data.to_string(), vec![]),
+        };
+        if channel_ids.is_empty() {
+            channel_ids.push(component.channel_id);
+        }
+        return subscribe_confirm_component(ctx, component, playlist_id, channel_ids).await;
+    }
+    if let Some(data) = component.data.custom_id.strip_prefix("retry:subscribe:") {
+        if let Some((channel_ids, url)) = data.split_once(':') {
+            let channel_ids = channel_ids
+                .split(',')
+                .filter_map(|c| c.parse().ok().map(ChannelId::new))
+                .collect();
+            let url = url.to_string();
+            return retry_subscribe_component(ctx, component, channel_ids, url).await;
+        }
+    }
+    if let Some(url) = component.data.custom_id.strip_prefix("retry:unsubscribe:") {
+        let url = url.to_string();
+        return retry_unsubscribe_component(ctx, component, url).await;
+    }
+    if let Some(data) = component.data.custom_id.strip_prefix("setall:") {
+        if let Some((setting, Ok(enabled))) = data
+            .split_once(':')
+            .map(|(setting, enabled)| (setting.to_string(), enabled.parse::<bool>()))
+        {
+            return setall_confirm_component(ctx, component, setting, enabled).await;
+        }
+    }
     // Add any custom components here
     match component.data.custom_id.as_str() {
         "refresh_ping" => ping_refresh_component(ctx, component).await,
+        "resetcursors" => resetcursors_confirm_component(ctx, component).await,
         _ => nyi_component(ctx, component).await,
     }
 }
@@ -34,6 +90,35 @@ async fn ping_refresh_component(
     ctx: Context,
     component: ComponentInteraction,
 ) -> Result<(), SerenityError> {
+    let cooldown_remaining = {
+        let mut last_refresh = LAST_REFRESH.lock().await;
+        // Forget anything that's already off cooldown, so this doesn't grow forever
+        last_refresh.retain(|_, t| t.elapsed() < REFRESH_COOLDOWN);
+        let key = (component.message.id, component.user.id);
+        match last_refresh.get(&key) {
+            Some(t) => Some(REFRESH_COOLDOWN.saturating_sub(t.elapsed())),
+            None => {
+                last_refresh.insert(key, Instant::now());
+                None
+            }
+        }
+    };
+    if let Some(remaining) = cooldown_remaining {
+        return component
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content(format!(
+                            "Please wait {:.1}s before refreshing again.",
+                            remaining.as_secs_f32()
+                        ))
+                        .ephemeral(true),
+                ),
+            )
+            .await;
+    }
+
     let start_time = Instant::now();
     // Use awaiting the defer as a delay to calculate the ping.
     // This gives very inconsistent results, but imo is probably closer to what you want than a heartbeat ping.
@@ -46,3 +131,165 @@ async fn ping_refresh_component(
         .await?;
     Ok(())
 }
+
+async fn subscribe_confirm_component(
+    ctx: Context,
+    component: ComponentInteraction,
+    playlist_id: String,
+    channel_ids: Vec<ChannelId>,
+) -> Result<(), SerenityError> {
+    let mut lines = Vec::new();
+    for channel_id in channel_ids {
+        // Checked again here, in case other subscriptions were confirmed since the prompt was shown
+        lines.push(match guild_limit_reached(component.guild_id).await {
+            Some(reason) => reason,
+            None => match add_channel(&playlist_id, channel_id, component.guild_id).await {
+                Ok(r) if r.rows_affected() == 0 => format!(
+                    "Channel {} is already subscribed to uploads playlist {}.",
+                    channel_id.get(),
+                    playlist_id
+                ),
+                Ok(_) => format!(
+                    "Successfully subscribed channel {} to uploads playlist {}.",
+                    channel_id.get(),
+                    playlist_id
+                ),
+                Err(e) => format!(
+                    "Failed to add entry for channel {} to database: {}",
+                    channel_id.get(),
+                    e
+                ),
+            },
+        });
+    }
+    let content = lines.join("\n");
+    // Replaces the preview, which also removes the confirm button so it can't be clicked twice.
+    component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .components(vec![]),
+            ),
+        )
+        .await
+}
+
+async fn setall_confirm_component(
+    ctx: Context,
+    component: ComponentInteraction,
+    setting: String,
+    enabled: bool,
+) -> Result<(), SerenityError> {
+    // set_channel_setting panics on anything else, and custom_ids can't be entirely trusted
+    if !BOOL_SETTINGS.contains(&setting.as_str()) {
+        return nyi_component(ctx, component).await;
+    }
+    // For the same reason, check again that whoever pressed it could have run /setfilters_all
+    let allowed = component
+        .member
+        .as_ref()
+        .and_then(|m| m.permissions)
+        .is_some_and(|p| p.contains(MANAGE_PERMISSIONS));
+    let content = if !allowed {
+        "You do not have permission.".to_string()
+    } else {
+        match set_channel_setting(component.channel_id, &setting, enabled).await {
+            Ok(r) => format!(
+                "Turned `{}` {} for {} subscriptions in channel {}.",
+                setting,
+                if enabled { "on" } else { "off" },
+                r.rows_affected(),
+                component.channel_id.get()
+            ),
+            Err(e) => format!("Failed to update database: {}", e),
+        }
+    };
+    component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .components(vec![]),
+            ),
+        )
+        .await
+}
+
+async fn resetcursors_confirm_component(
+    ctx: Context,
+    component: ComponentInteraction,
+) -> Result<(), SerenityError> {
+    let content = if !is_admin_user(component.user.id) {
+        "You do not have permission.".to_string()
+    } else {
+        match reset_channel_cursors(component.channel_id).await {
+            Ok(r) => format!(
+                "Reset {} cursors in channel {} to now.",
+                r.rows_affected(),
+                component.channel_id.get()
+            ),
+            Err(e) => format!("Failed to update database: {}", e),
+        }
+    };
+    component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .components(vec![]),
+            ),
+        )
+        .await
+}
+
+// Resolving can take a while, so these acknowledge first and edit the failed message afterwards.
+// If it fails again, the retry button stays where it is.
+async fn retry_subscribe_component(
+    ctx: Context,
+    component: ComponentInteraction,
+    channel_ids: Vec<ChannelId>,
+    url: String,
+) -> Result<(), SerenityError> {
+    component.defer(&ctx.http).await?;
+    let prompt = match resolve_playlist_id(&url).await {
+        Ok(playlist_id) => {
+            subscribe_prompt(
+                &playlist_id,
+                &channel_ids,
+                component.channel_id,
+                component.guild_id.is_some(),
+                parse_playlist_id(&url).is_some(),
+            )
+            .await
+        }
+        Err(message) => Err(message),
+    };
+    let response = match prompt {
+        Ok((content, row)) => EditInteractionResponse::new()
+            .content(content)
+            .components(vec![row]),
+        Err(message) => EditInteractionResponse::new().content(message),
+    };
+    component.edit_response(&ctx.http, response).await?;
+    Ok(())
+}
+
+async fn retry_unsubscribe_component(
+    ctx: Context,
+    component: ComponentInteraction,
+    url: String,
+) -> Result<(), SerenityError> {
+    component.defer(&ctx.http).await?;
+    let response = match resolve_playlist_id(&url).await {
+        Ok(playlist_id) => EditInteractionResponse::new()
+            .content(unsubscribe_channel(&playlist_id, component.channel_id).await)
+            .components(vec![]),
+        Err(message) => EditInteractionResponse::new().content(message),
+    };
+    component.edit_response(&ctx.http, response).await?;
+    Ok(())
+}
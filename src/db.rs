@@ -1,8 +1,12 @@
 use crate::DB;
 
-use google_youtube3::chrono::{DateTime, SecondsFormat, Utc};
-use serenity::all::ChannelId;
-use sqlx::{query, sqlite::SqliteQueryResult, Row};
+use google_youtube3::chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
+use serenity::all::{ChannelId, GuildId};
+use sqlx::{
+    query,
+    sqlite::{SqliteQueryResult, SqliteRow},
+    Row, SqlitePool,
+};
 
 fn into_sqlite(dt: &DateTime<Utc>) -> String {
     dt.to_rfc3339_opts(SecondsFormat::Secs, true)
@@ -11,24 +15,243 @@ fn into_sqlite(dt: &DateTime<Utc>) -> String {
         .replace('T', " ")
 }
 
-#[allow(dead_code)]
 fn from_sqlite(str: &str) -> DateTime<Utc> {
     DateTime::parse_from_rfc3339(&format!("{}Z", str))
         .unwrap()
         .into()
 }
 
+// Each of these upgrades the schema by one version, and PRAGMA user_version records how many have been applied.
+// So only ever append to this list, never edit or reorder it!
+const MIGRATIONS: &[&str] = &[
+    "ALTER TABLE channels ADD COLUMN unlisted_allowed INTEGER NOT NULL DEFAULT 0",
+    "ALTER TABLE channels ADD COLUMN suppress_embeds INTEGER NOT NULL DEFAULT 0",
+    "ALTER TABLE channels ADD COLUMN awaiting_first INTEGER NOT NULL DEFAULT 0",
+    "ALTER TABLE channels ADD COLUMN show_stats INTEGER NOT NULL DEFAULT 0",
+    "ALTER TABLE guild_settings ADD COLUMN default_channel INTEGER",
+    "ALTER TABLE channels ADD COLUMN guild_id INTEGER",
+    "ALTER TABLE channels ADD COLUMN notify_interval INTEGER NOT NULL DEFAULT 0",
+    "ALTER TABLE channels ADD COLUMN last_notified TEXT CHECK ( DATETIME(last_notified) IS last_notified )",
+    "ALTER TABLE channels ADD COLUMN skip_age_restricted INTEGER NOT NULL DEFAULT 0",
+    "ALTER TABLE channels ADD COLUMN live_alerts INTEGER NOT NULL DEFAULT 0",
+    "ALTER TABLE channels ADD COLUMN live_video_id TEXT",
+    "ALTER TABLE channels ADD COLUMN subscribed_at TEXT CHECK ( DATETIME(subscribed_at) IS subscribed_at )",
+    // Close enough for existing subscriptions, which were at least this far along
+    "UPDATE channels SET subscribed_at = most_recent",
+    "ALTER TABLE channels ADD COLUMN dedup_titles INTEGER NOT NULL DEFAULT 0",
+    "ALTER TABLE channels ADD COLUMN use_thread INTEGER NOT NULL DEFAULT 0",
+    "ALTER TABLE channels ADD COLUMN thread_id INTEGER",
+    "ALTER TABLE channels ADD COLUMN show_chapters INTEGER NOT NULL DEFAULT 0",
+    "ALTER TABLE channels ADD COLUMN batch_window INTEGER NOT NULL DEFAULT 0",
+    "ALTER TABLE channels ADD COLUMN reactions TEXT NOT NULL DEFAULT ''",
+    "ALTER TABLE channels ADD COLUMN daily_digest INTEGER NOT NULL DEFAULT 0",
+];
+
+// Every statement here must be safe to run on every startup,
+// so that databases created by older versions pick up new tables.
+pub async fn update_db_schema(db: &SqlitePool) -> Result<(), sqlx::Error> {
+    query(
+        "CREATE TABLE IF NOT EXISTS channels (
+            playlist_id TEXT NOT NULL,
+            channel_id INTEGER NOT NULL,
+            most_recent TEXT NOT NULL CHECK ( DATETIME(most_recent) IS most_recent ),
+            PRIMARY KEY (playlist_id, channel_id)
+        ) STRICT",
+    )
+    .execute(db)
+    .await?;
+    // Journal of notifications that are about to be sent, but whose cursor hasn't been advanced yet.
+    query(
+        "CREATE TABLE IF NOT EXISTS pending_sends (
+            playlist_id TEXT NOT NULL,
+            channel_id INTEGER NOT NULL,
+            video_id TEXT NOT NULL,
+            published_at TEXT NOT NULL CHECK ( DATETIME(published_at) IS published_at ),
+            PRIMARY KEY (playlist_id, channel_id, video_id)
+        ) STRICT",
+    )
+    .execute(db)
+    .await?;
+    // Videos each subscription was recently notified about, in case a playlist reorders or re-adds one.
+    query(
+        "CREATE TABLE IF NOT EXISTS notified_videos (
+            playlist_id TEXT NOT NULL,
+            channel_id INTEGER NOT NULL,
+            video_id TEXT NOT NULL,
+            notified_at TEXT NOT NULL CHECK ( DATETIME(notified_at) IS notified_at ),
+            PRIMARY KEY (playlist_id, channel_id, video_id)
+        ) STRICT",
+    )
+    .execute(db)
+    .await?;
+    // Normalized titles of recent notifications, for subscriptions with dedup_titles on.
+    query(
+        "CREATE TABLE IF NOT EXISTS notified_titles (
+            playlist_id TEXT NOT NULL,
+            channel_id INTEGER NOT NULL,
+            title TEXT NOT NULL,
+            notified_at TEXT NOT NULL CHECK ( DATETIME(notified_at) IS notified_at ),
+            PRIMARY KEY (playlist_id, channel_id, title)
+        ) STRICT",
+    )
+    .execute(db)
+    .await?;
+    // At most one row, naming the instance that's allowed to send notifications.
+    query(
+        "CREATE TABLE IF NOT EXISTS bot_lock (
+            id INTEGER PRIMARY KEY CHECK ( id == 0 ),
+            instance TEXT NOT NULL,
+            heartbeat TEXT NOT NULL CHECK ( DATETIME(heartbeat) IS heartbeat )
+        ) STRICT",
+    )
+    .execute(db)
+    .await?;
+    // When each playlist was last successfully checked, so that it survives restarts.
+    query(
+        "CREATE TABLE IF NOT EXISTS playlist_state (
+            playlist_id TEXT PRIMARY KEY,
+            last_polled TEXT NOT NULL CHECK ( DATETIME(last_polled) IS last_polled )
+        ) STRICT",
+    )
+    .execute(db)
+    .await?;
+    // Videos waiting for their channel's next daily digest, from subscriptions with daily_digest on.
+    // Their cursors have already moved past them, so this is the only place they're kept.
+    query(
+        "CREATE TABLE IF NOT EXISTS digest_queue (
+            channel_id INTEGER NOT NULL,
+            video_id TEXT NOT NULL,
+            playlist_id TEXT NOT NULL,
+            creator TEXT NOT NULL,
+            title TEXT NOT NULL,
+            queued_at TEXT NOT NULL CHECK ( DATETIME(queued_at) IS queued_at ),
+            PRIMARY KEY (channel_id, video_id)
+        ) STRICT",
+    )
+    .execute(db)
+    .await?;
+    query(
+        "CREATE TABLE IF NOT EXISTS guild_settings (
+            guild_id INTEGER PRIMARY KEY,
+            timezone TEXT NOT NULL DEFAULT 'UTC'
+        ) STRICT",
+    )
+    .execute(db)
+    .await?;
+
+    let version: i64 = query("PRAGMA user_version")
+        .fetch_one(db)
+        .await?
+        .try_get(0)?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let mut tx = db.begin().await?;
+        query(migration).execute(&mut *tx).await?;
+        query(&format!("PRAGMA user_version = {}", i + 1))
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+    }
+    Ok(())
+}
+
+// Per-subscription options, which are columns of the channels table.
+#[derive(Clone)]
+pub struct SubscriptionSettings {
+    pub unlisted_allowed: bool,
+    pub suppress_embeds: bool,
+    // Cleared by the update loop once the first notification (with its welcome line) is sent.
+    pub awaiting_first: bool,
+    pub show_stats: bool,
+    // Minimum minutes between notifications, with anything in between held back for a digest. 0 means no minimum.
+    pub notify_interval: i64,
+    pub last_notified: Option<DateTime<Utc>>,
+    pub skip_age_restricted: bool,
+    // Skip videos whose title matches one that was announced within title_dedup_window, i.e. re-uploads.
+    pub dedup_titles: bool,
+    // Post into a thread named after the creator, instead of straight into the channel.
+    pub use_thread: bool,
+    // That thread, once it's been created.
+    pub thread_id: Option<ChannelId>,
+    pub live_alerts: bool,
+    // List the first few chapters from the video's description.
+    pub show_chapters: bool,
+    // Minutes to wait after a video is published for more to send along with it. 0 sends right away.
+    pub batch_window: i64,
+    // Emojis to react to each notification with, either unicode or custom ones as <:name:id>.
+    pub reactions: Vec<String>,
+    // Collect videos into one message per day at digest_time, instead of sending each one.
+    pub daily_digest: bool,
+}
+
+// Every column of SubscriptionSettings, in the order read_settings expects them.
+const SETTINGS_COLUMNS: &str = "unlisted_allowed, suppress_embeds, awaiting_first, show_stats,
+    notify_interval, last_notified, skip_age_restricted, dedup_titles, use_thread, thread_id, live_alerts,
+    show_chapters, batch_window, reactions, daily_digest";
+
+// start is the index of the first of SETTINGS_COLUMNS in the row.
+fn read_settings(s: &SqliteRow, start: usize) -> Result<SubscriptionSettings, sqlx::Error> {
+    Ok(SubscriptionSettings {
+        unlisted_allowed: s.try_get(start)?,
+        suppress_embeds: s.try_get(start + 1)?,
+        awaiting_first: s.try_get(start + 2)?,
+        show_stats: s.try_get(start + 3)?,
+        notify_interval: s.try_get(start + 4)?,
+        last_notified: s.try_get::<Option<&str>, _>(start + 5)?.map(from_sqlite),
+        skip_age_restricted: s.try_get(start + 6)?,
+        dedup_titles: s.try_get(start + 7)?,
+        use_thread: s.try_get(start + 8)?,
+        thread_id: s
+            .try_get::<Option<i64>, _>(start + 9)?
+            .map(|id| ChannelId::new(id as u64)),
+        live_alerts: s.try_get(start + 10)?,
+        show_chapters: s.try_get(start + 11)?,
+        batch_window: s.try_get(start + 12)?,
+        reactions: s
+            .try_get::<&str, _>(start + 13)?
+            .split_whitespace()
+            .map(String::from)
+            .collect(),
+        daily_digest: s.try_get(start + 14)?,
+    })
+}
+
+// Column names can't be bound as parameters, so set_subscription_setting only accepts one of these.
+pub const BOOL_SETTINGS: [&str; 10] = [
+    "unlisted_allowed",
+    "suppress_embeds",
+    "awaiting_first",
+    "show_stats",
+    "skip_age_restricted",
+    "live_alerts",
+    "dedup_titles",
+    "use_thread",
+    "show_chapters",
+    "daily_digest",
+];
+
+pub struct Subscription {
+    pub playlist_id: String,
+    pub channel_id: ChannelId,
+    pub most_recent: DateTime<Utc>,
+    pub settings: SubscriptionSettings,
+}
+
+// Subscribing twice is a no-op rather than an error, so check rows_affected() to tell the difference.
+// guild_id is None for DMs.
 pub async fn add_channel(
     playlist_id: &String,
     channel_id: ChannelId,
+    guild_id: Option<GuildId>,
 ) -> Result<SqliteQueryResult, sqlx::Error> {
     query(
-        "INSERT INTO channels (playlist_id, channel_id, most_recent)
-            VALUES ($1, $2, $3)",
+        "INSERT INTO channels (playlist_id, channel_id, most_recent, guild_id, subscribed_at)
+            VALUES ($1, $2, $3, $4, $3)
+            ON CONFLICT (playlist_id, channel_id) DO NOTHING",
     )
     .bind(playlist_id)
     .bind(channel_id.get() as i64)
     .bind(into_sqlite(&Utc::now()))
+    .bind(guild_id.map(|g| g.get() as i64))
     .execute(DB.get().unwrap())
     .await
 }
@@ -45,12 +268,126 @@ pub async fn get_num_playlists() -> Result<u32, sqlx::Error> {
     .try_get(0)
 }
 
-pub async fn get_playlists() -> Result<Vec<String>, sqlx::Error> {
+// Subscriptions from before guild ids were stored, plus DMs (which never have one).
+pub async fn get_channels_without_guild() -> Result<Vec<ChannelId>, sqlx::Error> {
+    query(
+        "SELECT DISTINCT channel_id
+            FROM channels
+            WHERE guild_id IS NULL",
+    )
+    .fetch_all(DB.get().unwrap())
+    .await?
+    .into_iter()
+    .map(|s| Ok(ChannelId::new(s.try_get(0)?)))
+    .collect()
+}
+
+pub async fn set_channel_guild(
+    channel_id: ChannelId,
+    guild_id: GuildId,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "UPDATE channels
+            SET guild_id = $1
+            WHERE channel_id == $2",
+    )
+    .bind(guild_id.get() as i64)
+    .bind(channel_id.get() as i64)
+    .execute(DB.get().unwrap())
+    .await
+}
+
+pub async fn get_num_guild_subscriptions(guild_id: GuildId) -> Result<u32, sqlx::Error> {
+    query(
+        "SELECT COUNT(*)
+            FROM channels
+            WHERE guild_id == $1",
+    )
+    .bind(guild_id.get() as i64)
+    .fetch_one(DB.get().unwrap())
+    .await?
+    .try_get(0)
+}
+
+// By default, least recently checked first (never checked before all of those), so that a restart picks up where it left off.
+// by_last_polled = false is the old fixed order, by playlist id.
+pub async fn get_playlists(by_last_polled: bool) -> Result<Vec<String>, sqlx::Error> {
+    query(if by_last_polled {
+        "SELECT DISTINCT channels.playlist_id
+            FROM channels
+            LEFT JOIN playlist_state USING (playlist_id)
+            ORDER BY playlist_state.last_polled, channels.playlist_id"
+    } else {
+        "SELECT DISTINCT playlist_id
+            FROM channels
+            ORDER BY playlist_id"
+    })
+    .fetch_all(DB.get().unwrap())
+    .await?
+    .into_iter()
+    .map(|s| s.try_get(0))
+    .collect()
+}
+
+pub async fn set_last_polled(
+    playlist_id: &String,
+    last_polled: &DateTime<Utc>,
+) -> Result<SqliteQueryResult, sqlx::Error> {
     query(
-        "SELECT DISTINCT playlist_id 
-            FROM channels 
+        "INSERT INTO playlist_state (playlist_id, last_polled)
+            VALUES ($1, $2)
+            ON CONFLICT (playlist_id) DO UPDATE SET last_polled = excluded.last_polled",
+    )
+    .bind(playlist_id)
+    .bind(into_sqlite(last_polled))
+    .execute(DB.get().unwrap())
+    .await
+}
+
+// None if any subscribed playlist has never been checked at all (or there are no subscriptions).
+pub async fn get_oldest_last_polled() -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let row = query(
+        "SELECT MIN(playlist_state.last_polled), COUNT(*) == COUNT(playlist_state.last_polled)
+            FROM (SELECT DISTINCT playlist_id FROM channels)
+            LEFT JOIN playlist_state USING (playlist_id)",
+    )
+    .fetch_one(DB.get().unwrap())
+    .await?;
+    let all_polled: bool = row.try_get(1)?;
+    let oldest: Option<&str> = row.try_get(0)?;
+    Ok(oldest.filter(|_| all_polled).map(from_sqlite))
+}
+
+pub async fn get_subscriptions() -> Result<Vec<Subscription>, sqlx::Error> {
+    query(&format!(
+        "SELECT playlist_id, channel_id, most_recent, {}
+            FROM channels
+            ORDER BY channel_id, playlist_id",
+        SETTINGS_COLUMNS
+    ))
+    .fetch_all(DB.get().unwrap())
+    .await?
+    .into_iter()
+    .map(|row| {
+        Ok(Subscription {
+            playlist_id: row.try_get(0)?,
+            channel_id: ChannelId::new(row.try_get(1)?),
+            most_recent: from_sqlite(row.try_get(2)?),
+            settings: read_settings(&row, 3)?,
+        })
+    })
+    .collect()
+}
+
+// Ordered by playlist id, so that /list numbers stay the same until the subscriptions themselves change.
+pub async fn get_channel_playlists(channel_id: ChannelId) -> Result<Vec<String>, sqlx::Error> {
+    query(
+        "SELECT playlist_id
+            FROM channels
+            WHERE channel_id == $1
             ORDER BY playlist_id",
     )
+    .bind(channel_id.get() as i64)
     .fetch_all(DB.get().unwrap())
     .await?
     .into_iter()
@@ -58,26 +395,123 @@ pub async fn get_playlists() -> Result<Vec<String>, sqlx::Error> {
     .collect()
 }
 
+// YouTube sometimes only lists a video well after its publishedAt, by which point a newer video may have
+// already moved the cursor past it. Those still get sent, as long as they haven't been seen yet.
+// This has to stay well under NOTIFIED_VIDEO_RETENTION, or pruned videos would be sent again.
+const LATE_VIDEO_WINDOW: TimeDelta = TimeDelta::days(7);
+
 pub async fn get_channels_to_send(
     playlist_id: &String,
+    video_id: &String,
     published_at: &DateTime<Utc>,
-) -> Result<Vec<ChannelId>, sqlx::Error> {
-    query(
-        "SELECT DISTINCT channel_id 
+) -> Result<Vec<(ChannelId, SubscriptionSettings)>, sqlx::Error> {
+    query(&format!(
+        "SELECT DISTINCT channel_id, {}
             FROM channels
             WHERE playlist_id == $1
-            AND most_recent < $2",
-    )
+            AND (
+                most_recent < $2
+                OR (
+                    subscribed_at < $2
+                    AND $2 > $4
+                    AND NOT EXISTS (
+                        SELECT 1
+                            FROM notified_videos
+                            WHERE notified_videos.playlist_id == channels.playlist_id
+                            AND notified_videos.channel_id == channels.channel_id
+                            AND notified_videos.video_id == $3
+                    )
+                )
+            )",
+        SETTINGS_COLUMNS
+    ))
     .bind(playlist_id)
     .bind(into_sqlite(published_at))
+    .bind(video_id)
+    .bind(into_sqlite(&(Utc::now() - LATE_VIDEO_WINDOW)))
     .fetch_all(DB.get().unwrap())
     .await
     .unwrap()
     .into_iter()
-    .map(|s| Ok(ChannelId::new(s.try_get(0)?)))
+    .map(|s| Ok((ChannelId::new(s.try_get(0)?), read_settings(&s, 1)?)))
+    .collect()
+}
+
+// Everything about one subscription, for /settings. None if the channel isn't subscribed to the playlist.
+pub async fn get_subscription_settings(
+    playlist_id: &String,
+    channel_id: &ChannelId,
+) -> Result<Option<(DateTime<Utc>, SubscriptionSettings)>, sqlx::Error> {
+    query(&format!(
+        "SELECT most_recent, {}
+            FROM channels
+            WHERE playlist_id == $1
+            AND channel_id == $2",
+        SETTINGS_COLUMNS
+    ))
+    .bind(playlist_id)
+    .bind(channel_id.get() as i64)
+    .fetch_optional(DB.get().unwrap())
+    .await?
+    .map(|s| Ok((from_sqlite(s.try_get(0)?), read_settings(&s, 1)?)))
+    .transpose()
+}
+
+// Subscriptions with live_alerts on, and the stream each one was last alerted about, if it's still live.
+pub async fn get_live_subscriptions(
+    playlist_id: &String,
+) -> Result<Vec<(ChannelId, Option<String>)>, sqlx::Error> {
+    query(
+        "SELECT channel_id, live_video_id
+            FROM channels
+            WHERE playlist_id == $1
+            AND live_alerts == 1",
+    )
+    .bind(playlist_id)
+    .fetch_all(DB.get().unwrap())
+    .await?
+    .into_iter()
+    .map(|s| Ok((ChannelId::new(s.try_get(0)?), s.try_get(1)?)))
     .collect()
 }
 
+pub async fn set_live_video_id(
+    playlist_id: &String,
+    channel_id: &ChannelId,
+    video_id: Option<&str>,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "UPDATE channels
+            SET live_video_id = $1
+            WHERE playlist_id == $2
+            AND channel_id == $3",
+    )
+    .bind(video_id)
+    .bind(playlist_id)
+    .bind(channel_id.get() as i64)
+    .execute(DB.get().unwrap())
+    .await
+}
+
+pub async fn set_thread_id(
+    playlist_id: &String,
+    channel_id: &ChannelId,
+    thread_id: Option<ChannelId>,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "UPDATE channels
+            SET thread_id = $1
+            WHERE playlist_id == $2
+            AND channel_id == $3",
+    )
+    .bind(thread_id.map(|t| t.get() as i64))
+    .bind(playlist_id)
+    .bind(channel_id.get() as i64)
+    .execute(DB.get().unwrap())
+    .await
+}
+
+// Never moves the cursor backwards, since a late video can be older than one that was already sent.
 pub async fn update_most_recent(
     playlist_id: &String,
     channel_id: &ChannelId,
@@ -85,7 +519,44 @@ pub async fn update_most_recent(
 ) -> Result<SqliteQueryResult, sqlx::Error> {
     query(
         "UPDATE channels
-            SET most_recent = $1
+            SET most_recent = MAX(most_recent, $1)
+            WHERE playlist_id == $2
+            AND channel_id == $3",
+    )
+    .bind(into_sqlite(new_value))
+    .bind(playlist_id)
+    .bind(channel_id.get() as i64)
+    .execute(DB.get().unwrap())
+    .await
+}
+
+// For a fresh start: nothing from before now will be sent to any of the channel's subscriptions.
+// subscribed_at moves too, or late videos from before now could still be sent.
+pub async fn reset_channel_cursors(
+    channel_id: ChannelId,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "UPDATE channels
+            SET most_recent = $1, subscribed_at = $1
+            WHERE channel_id == $2",
+    )
+    .bind(into_sqlite(&Utc::now()))
+    .bind(channel_id.get() as i64)
+    .execute(DB.get().unwrap())
+    .await
+}
+
+// Unlike update_most_recent, this can rewind the cursor.
+// Moving it forward moves subscribed_at along with it, same as reset_channel_cursors,
+// or late videos from before the new cursor could still be sent. Rewinding doesn't need to touch it.
+pub async fn set_most_recent(
+    playlist_id: &String,
+    channel_id: &ChannelId,
+    new_value: &DateTime<Utc>,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "UPDATE channels
+            SET most_recent = $1, subscribed_at = MAX(subscribed_at, $1)
             WHERE playlist_id == $2
             AND channel_id == $3",
     )
@@ -96,17 +567,543 @@ pub async fn update_most_recent(
     .await
 }
 
+// Also drops anything it had waiting for the daily digest.
 pub async fn delete_channel(
     playlist_id: &String,
     channel_id: ChannelId,
 ) -> Result<SqliteQueryResult, sqlx::Error> {
+    let mut tx = DB.get().unwrap().begin().await?;
     query(
+        "DELETE FROM digest_queue
+            WHERE playlist_id == $1
+            AND channel_id == $2",
+    )
+    .bind(playlist_id)
+    .bind(channel_id.get() as i64)
+    .execute(&mut *tx)
+    .await?;
+    let result = query(
         "DELETE FROM channels
             WHERE playlist_id == $1
             AND channel_id == $2",
     )
     .bind(playlist_id)
     .bind(channel_id.get() as i64)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(result)
+}
+
+// For when the Discord channel itself is gone.
+pub async fn delete_all_subscriptions(
+    channel_id: ChannelId,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    let mut tx = DB.get().unwrap().begin().await?;
+    query(
+        "DELETE FROM digest_queue
+            WHERE channel_id == $1",
+    )
+    .bind(channel_id.get() as i64)
+    .execute(&mut *tx)
+    .await?;
+    let result = query(
+        "DELETE FROM channels
+            WHERE channel_id == $1",
+    )
+    .bind(channel_id.get() as i64)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(result)
+}
+
+pub async fn add_digest_video(
+    playlist_id: &String,
+    channel_id: &ChannelId,
+    video_id: &String,
+    creator: &str,
+    title: &str,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "INSERT OR IGNORE INTO digest_queue (channel_id, video_id, playlist_id, creator, title, queued_at)
+            VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(channel_id.get() as i64)
+    .bind(video_id)
+    .bind(playlist_id)
+    .bind(creator)
+    .bind(title)
+    .bind(into_sqlite(&Utc::now()))
+    .execute(DB.get().unwrap())
+    .await
+}
+
+// Each channel with anything queued, along with its server's timezone (if it has one) and its oldest queued video.
+pub async fn get_digest_channels(
+) -> Result<Vec<(ChannelId, Option<String>, DateTime<Utc>)>, sqlx::Error> {
+    query(
+        "SELECT channel_id,
+                (SELECT timezone
+                    FROM guild_settings
+                    JOIN channels USING (guild_id)
+                    WHERE channels.channel_id == digest_queue.channel_id
+                    LIMIT 1),
+                MIN(queued_at)
+            FROM digest_queue
+            GROUP BY channel_id",
+    )
+    .fetch_all(DB.get().unwrap())
+    .await?
+    .into_iter()
+    .map(|s| {
+        Ok((
+            ChannelId::new(s.try_get(0)?),
+            s.try_get(1)?,
+            from_sqlite(s.try_get(2)?),
+        ))
+    })
+    .collect()
+}
+
+pub struct DigestVideo {
+    pub video_id: String,
+    pub creator: String,
+    pub title: String,
+}
+
+// Grouped by creator, and oldest first within each creator.
+pub async fn get_digest_videos(
+    channel_id: ChannelId,
+    queued_before: &DateTime<Utc>,
+) -> Result<Vec<DigestVideo>, sqlx::Error> {
+    query(
+        "SELECT video_id, creator, title
+            FROM digest_queue
+            WHERE channel_id == $1
+            AND queued_at < $2
+            ORDER BY creator, queued_at",
+    )
+    .bind(channel_id.get() as i64)
+    .bind(into_sqlite(queued_before))
+    .fetch_all(DB.get().unwrap())
+    .await?
+    .into_iter()
+    .map(|s| {
+        Ok(DigestVideo {
+            video_id: s.try_get(0)?,
+            creator: s.try_get(1)?,
+            title: s.try_get(2)?,
+        })
+    })
+    .collect()
+}
+
+pub async fn delete_digest_video(
+    channel_id: ChannelId,
+    video_id: &String,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "DELETE FROM digest_queue
+            WHERE channel_id == $1
+            AND video_id == $2",
+    )
+    .bind(channel_id.get() as i64)
+    .bind(video_id)
+    .execute(DB.get().unwrap())
+    .await
+}
+
+pub async fn add_pending_send(
+    playlist_id: &String,
+    channel_id: &ChannelId,
+    video_id: &String,
+    published_at: &DateTime<Utc>,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "INSERT OR REPLACE INTO pending_sends (playlist_id, channel_id, video_id, published_at)
+            VALUES ($1, $2, $3, $4)",
+    )
+    .bind(playlist_id)
+    .bind(channel_id.get() as i64)
+    .bind(video_id)
+    .bind(into_sqlite(published_at))
+    .execute(DB.get().unwrap())
+    .await
+}
+
+pub async fn delete_pending_send(
+    playlist_id: &String,
+    channel_id: &ChannelId,
+    video_id: &String,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "DELETE FROM pending_sends
+            WHERE playlist_id == $1
+            AND channel_id == $2
+            AND video_id == $3",
+    )
+    .bind(playlist_id)
+    .bind(channel_id.get() as i64)
+    .bind(video_id)
+    .execute(DB.get().unwrap())
+    .await
+}
+
+pub async fn add_notified_video(
+    playlist_id: &String,
+    channel_id: &ChannelId,
+    video_id: &String,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "INSERT OR REPLACE INTO notified_videos (playlist_id, channel_id, video_id, notified_at)
+            VALUES ($1, $2, $3, $4)",
+    )
+    .bind(playlist_id)
+    .bind(channel_id.get() as i64)
+    .bind(video_id)
+    .bind(into_sqlite(&Utc::now()))
+    .execute(DB.get().unwrap())
+    .await
+}
+
+pub async fn was_notified(
+    playlist_id: &String,
+    channel_id: &ChannelId,
+    video_id: &String,
+) -> Result<bool, sqlx::Error> {
+    query(
+        "SELECT 1
+            FROM notified_videos
+            WHERE playlist_id == $1
+            AND channel_id == $2
+            AND video_id == $3",
+    )
+    .bind(playlist_id)
+    .bind(channel_id.get() as i64)
+    .bind(video_id)
+    .fetch_optional(DB.get().unwrap())
+    .await
+    .map(|row| row.is_some())
+}
+
+pub async fn prune_notified_videos(
+    older_than: &DateTime<Utc>,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "DELETE FROM notified_videos
+            WHERE notified_at < $1",
+    )
+    .bind(into_sqlite(older_than))
+    .execute(DB.get().unwrap())
+    .await
+}
+
+// Case and spacing often change between uploads of the same video, so they're ignored.
+fn normalize_title(title: &str) -> String {
+    title
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+pub async fn add_notified_title(
+    playlist_id: &String,
+    channel_id: &ChannelId,
+    title: &str,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "INSERT OR REPLACE INTO notified_titles (playlist_id, channel_id, title, notified_at)
+            VALUES ($1, $2, $3, $4)",
+    )
+    .bind(playlist_id)
+    .bind(channel_id.get() as i64)
+    .bind(normalize_title(title))
+    .bind(into_sqlite(&Utc::now()))
+    .execute(DB.get().unwrap())
+    .await
+}
+
+pub async fn was_title_notified(
+    playlist_id: &String,
+    channel_id: &ChannelId,
+    title: &str,
+    since: &DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    query(
+        "SELECT 1
+            FROM notified_titles
+            WHERE playlist_id == $1
+            AND channel_id == $2
+            AND title == $3
+            AND notified_at >= $4",
+    )
+    .bind(playlist_id)
+    .bind(channel_id.get() as i64)
+    .bind(normalize_title(title))
+    .bind(into_sqlite(since))
+    .fetch_optional(DB.get().unwrap())
+    .await
+    .map(|row| row.is_some())
+}
+
+pub async fn prune_notified_titles(
+    older_than: &DateTime<Utc>,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "DELETE FROM notified_titles
+            WHERE notified_at < $1",
+    )
+    .bind(into_sqlite(older_than))
+    .execute(DB.get().unwrap())
+    .await
+}
+
+// Anything left in pending_sends at startup was (probably) sent right before the bot went down,
+// so advance those cursors without sending again. Returns the number of cursors advanced.
+pub async fn reconcile_pending_sends() -> Result<u64, sqlx::Error> {
+    let mut tx = DB.get().unwrap().begin().await?;
+    let result = query(
+        "UPDATE channels
+            SET most_recent = (
+                SELECT MAX(published_at)
+                    FROM pending_sends
+                    WHERE pending_sends.playlist_id == channels.playlist_id
+                    AND pending_sends.channel_id == channels.channel_id
+            )
+            WHERE most_recent < (
+                SELECT MAX(published_at)
+                    FROM pending_sends
+                    WHERE pending_sends.playlist_id == channels.playlist_id
+                    AND pending_sends.channel_id == channels.channel_id
+            )",
+    )
+    .execute(&mut *tx)
+    .await?;
+    // The cursor alone doesn't cover late videos, which can be older than it.
+    query(
+        "INSERT OR IGNORE INTO notified_videos (playlist_id, channel_id, video_id, notified_at)
+            SELECT playlist_id, channel_id, video_id, $1
+                FROM pending_sends",
+    )
+    .bind(into_sqlite(&Utc::now()))
+    .execute(&mut *tx)
+    .await?;
+    query("DELETE FROM pending_sends").execute(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+// Takes (or refreshes) the lock if it's free, already ours, or its holder stopped heartbeating before stale_before.
+// Returns whether this instance holds the lock afterwards.
+pub async fn try_take_bot_lock(
+    instance: &str,
+    stale_before: &DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let result = query(
+        "INSERT INTO bot_lock (id, instance, heartbeat)
+            VALUES (0, $1, $2)
+            ON CONFLICT (id) DO UPDATE SET instance = excluded.instance, heartbeat = excluded.heartbeat
+            WHERE bot_lock.instance == excluded.instance
+            OR bot_lock.heartbeat < $3",
+    )
+    .bind(instance)
+    .bind(into_sqlite(&Utc::now()))
+    .bind(into_sqlite(stale_before))
+    .execute(DB.get().unwrap())
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn delete_bot_lock(instance: &str) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "DELETE FROM bot_lock
+            WHERE instance == $1",
+    )
+    .bind(instance)
+    .execute(DB.get().unwrap())
+    .await
+}
+
+pub async fn get_guild_timezone(guild_id: GuildId) -> Result<Option<String>, sqlx::Error> {
+    query(
+        "SELECT timezone
+            FROM guild_settings
+            WHERE guild_id == $1",
+    )
+    .bind(guild_id.get() as i64)
+    .fetch_optional(DB.get().unwrap())
+    .await?
+    .map(|row| row.try_get(0))
+    .transpose()
+}
+
+pub async fn set_guild_timezone(
+    guild_id: GuildId,
+    timezone: &str,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "INSERT INTO guild_settings (guild_id, timezone)
+            VALUES ($1, $2)
+            ON CONFLICT (guild_id) DO UPDATE SET timezone = excluded.timezone",
+    )
+    .bind(guild_id.get() as i64)
+    .bind(timezone)
+    .execute(DB.get().unwrap())
+    .await
+}
+
+pub async fn get_guild_default_channel(
+    guild_id: GuildId,
+) -> Result<Option<ChannelId>, sqlx::Error> {
+    Ok(query(
+        "SELECT default_channel
+            FROM guild_settings
+            WHERE guild_id == $1",
+    )
+    .bind(guild_id.get() as i64)
+    .fetch_optional(DB.get().unwrap())
+    .await?
+    .map(|row| row.try_get::<Option<i64>, _>(0))
+    .transpose()?
+    .flatten()
+    .map(|id| ChannelId::new(id as u64)))
+}
+
+// None goes back to subscribing whichever channel /subscribe is used in.
+pub async fn set_guild_default_channel(
+    guild_id: GuildId,
+    channel_id: Option<ChannelId>,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "INSERT INTO guild_settings (guild_id, default_channel)
+            VALUES ($1, $2)
+            ON CONFLICT (guild_id) DO UPDATE SET default_channel = excluded.default_channel",
+    )
+    .bind(guild_id.get() as i64)
+    .bind(channel_id.map(|c| c.get() as i64))
+    .execute(DB.get().unwrap())
+    .await
+}
+
+pub async fn set_notify_interval(
+    playlist_id: &String,
+    channel_id: ChannelId,
+    minutes: i64,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "UPDATE channels
+            SET notify_interval = $1
+            WHERE playlist_id == $2
+            AND channel_id == $3",
+    )
+    .bind(minutes)
+    .bind(playlist_id)
+    .bind(channel_id.get() as i64)
+    .execute(DB.get().unwrap())
+    .await
+}
+
+// Space separated, or empty for none.
+pub async fn set_reactions(
+    playlist_id: &String,
+    channel_id: ChannelId,
+    reactions: &str,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "UPDATE channels
+            SET reactions = $1
+            WHERE playlist_id == $2
+            AND channel_id == $3",
+    )
+    .bind(reactions)
+    .bind(playlist_id)
+    .bind(channel_id.get() as i64)
+    .execute(DB.get().unwrap())
+    .await
+}
+
+pub async fn set_batch_window(
+    playlist_id: &String,
+    channel_id: ChannelId,
+    minutes: i64,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "UPDATE channels
+            SET batch_window = $1
+            WHERE playlist_id == $2
+            AND channel_id == $3",
+    )
+    .bind(minutes)
+    .bind(playlist_id)
+    .bind(channel_id.get() as i64)
+    .execute(DB.get().unwrap())
+    .await
+}
+
+pub async fn set_last_notified(
+    playlist_id: &String,
+    channel_id: &ChannelId,
+    last_notified: &DateTime<Utc>,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    query(
+        "UPDATE channels
+            SET last_notified = $1
+            WHERE playlist_id == $2
+            AND channel_id == $3",
+    )
+    .bind(into_sqlite(last_notified))
+    .bind(playlist_id)
+    .bind(channel_id.get() as i64)
+    .execute(DB.get().unwrap())
+    .await
+}
+
+// Same as set_subscription_setting, but for every subscription in the channel at once.
+pub async fn set_channel_setting(
+    channel_id: ChannelId,
+    setting: &str,
+    value: bool,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    assert!(
+        BOOL_SETTINGS.contains(&setting),
+        "Unknown subscription setting {}",
+        setting
+    );
+    query(&format!(
+        "UPDATE channels
+            SET {} = $1
+            WHERE channel_id == $2",
+        setting
+    ))
+    .bind(value)
+    .bind(channel_id.get() as i64)
+    .execute(DB.get().unwrap())
+    .await
+}
+
+pub async fn set_subscription_setting(
+    playlist_id: &String,
+    channel_id: ChannelId,
+    setting: &str,
+    value: bool,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    assert!(
+        BOOL_SETTINGS.contains(&setting),
+        "Unknown subscription setting {}",
+        setting
+    );
+    query(&format!(
+        "UPDATE channels
+            SET {} = $1
+            WHERE playlist_id == $2
+            AND channel_id == $3",
+        setting
+    ))
+    .bind(value)
+    .bind(playlist_id)
+    .bind(channel_id.get() as i64)
     .execute(DB.get().unwrap())
     .await
 }
@@ -0,0 +1,95 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::cache::BoundedMap;
+use crate::{test_mode, CONFIG};
+
+// Keyed by a hash of the message: when it was last printed, how many times it's been suppressed since,
+// and the message itself, so flush_suppressed_errors has something to print.
+static RECENT_ERRORS: Mutex<BoundedMap<u64, (Instant, u64, String)>> =
+    Mutex::new(BoundedMap::new());
+
+// 0 turns off deduplication.
+pub fn log_dedup_window() -> Duration {
+    Duration::from_secs(
+        CONFIG
+            .get()
+            .unwrap()
+            .get_int("log_dedup_window")
+            .expect("Failed to parse log_dedup_window as an integer")
+            .try_into()
+            .expect("log_dedup_window must not be negative"),
+    )
+}
+
+// So test mode output can't be mistaken for the real bot's when both log to the same place
+fn with_test_prefix(message: String) -> String {
+    if test_mode() {
+        format!("[TEST] {}", message)
+    } else {
+        message
+    }
+}
+
+// For everything that isn't an error. Never deduplicated, since these are all one-off status updates.
+pub fn log_info(message: String) {
+    println!("{}", with_test_prefix(message));
+}
+
+// Prints message, unless the exact same message was already printed less than log_dedup_window ago.
+// The first repeat after the window is up gets printed along with how many were suppressed in between.
+pub fn log_error(message: String) {
+    let message = with_test_prefix(message);
+    let window = log_dedup_window();
+    if window.is_zero() {
+        println!("{}", message);
+        return;
+    }
+    let mut hasher = DefaultHasher::new();
+    message.hash(&mut hasher);
+    let key = hasher.finish();
+
+    let now = Instant::now();
+    let mut recent = RECENT_ERRORS.lock().unwrap();
+    match recent.get_mut(&key) {
+        Some((last, suppressed, _)) if now.duration_since(*last) < window => *suppressed += 1,
+        Some((last, suppressed, _)) => {
+            print_suppressed(&message, *suppressed, now.duration_since(*last));
+            *last = now;
+            *suppressed = 0;
+        }
+        None => {
+            println!("{}", message);
+            recent.insert(key, (now, 0, message));
+        }
+    }
+}
+
+fn print_suppressed(message: &str, suppressed: u64, elapsed: Duration) {
+    println!(
+        "{}\n\t(this error occurred {} more times in the previous {:?})",
+        message, suppressed, elapsed
+    );
+}
+
+// Without this, an error that stops happening never gets its suppressed repeats reported,
+// since log_error only reports them on the next occurrence. Meant to be called periodically.
+pub fn flush_suppressed_errors() {
+    let window = log_dedup_window();
+    let now = Instant::now();
+    let mut recent = RECENT_ERRORS.lock().unwrap();
+    let expired: Vec<u64> = recent
+        .iter()
+        .filter(|(_, (last, suppressed, _))| *suppressed > 0 && now.duration_since(*last) >= window)
+        .map(|(key, _)| *key)
+        .collect();
+    for key in expired {
+        if let Some((last, suppressed, message)) = recent.get_mut(&key) {
+            print_suppressed(message, *suppressed, now.duration_since(*last));
+            *last = now;
+            *suppressed = 0;
+        }
+    }
+}
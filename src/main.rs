@@ -2,10 +2,13 @@
 // you **shouldn't** need to modify this file at all, unless you want to use an interaction other than commands and components.
 // in that case, modify interaction_create below and create a separate module for it in another file.
 
+mod cache;
 mod commands;
 mod components;
 mod db;
 mod generate_components;
+mod log_limit;
+mod metrics;
 mod rate_limit;
 mod update_loop;
 mod youtube;
@@ -17,26 +20,48 @@ use google_youtube3::client::NoToken;
 use google_youtube3::{hyper, hyper_rustls, YouTube};
 
 use hyper::client::HttpConnector;
+use hyper::header::HeaderValue;
 use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
 
 use sqlx::migrate::MigrateDatabase;
-use sqlx::{query, Sqlite, SqlitePool};
-use update_loop::update_loop;
+use sqlx::{Sqlite, SqlitePool};
+use update_loop::{
+    digest_time, max_videos_per_cycle, prune_deleted_channels, release_bot_lock, resync_attempts,
+    startup_delay, title_dedup_window, update_loop, MAINTENANCE_MODES, POLL_ORDERS,
+};
 
+use std::collections::BTreeMap;
 use std::env;
-use std::time::Duration;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use tokio::sync::{mpsc, OnceCell};
 
-use serenity::all::{Context, EventHandler, GatewayIntents};
+use serenity::all::{
+    Channel, ConnectionStage, Context, EventHandler, GatewayIntents, Http, ResumedEvent,
+    ShardStageUpdateEvent,
+};
 use serenity::async_trait;
 use serenity::model::application::{Command, Interaction};
 use serenity::model::gateway::Ready;
-use serenity::model::id::UserId;
+use serenity::model::id::{ChannelId, GuildId, UserId};
 
-use config::{Config, ConfigError, File};
+use config::{Config, ConfigError, File, Value, ValueKind};
 
+use crate::cache::{cache_capacity, cache_ttl};
+use crate::db::{
+    add_channel, get_channels_without_guild, reconcile_pending_sends, set_channel_guild,
+    update_db_schema,
+};
+use crate::log_limit::{log_dedup_window, log_error, log_info};
+use crate::metrics::{record_shard_connected, serve_metrics};
 use crate::rate_limit::RateLimiter;
+use crate::youtube::{
+    api_timeout, get_upload_playlist_id, get_uploads_from_playlist, get_videos_extras, verify_key,
+    ApiError, YoutubeApi, PLAYLIST_ID_STRATEGIES, PUBLISHED_AT_SOURCES,
+};
 
 // Technically this initial vec is never used but it makes it so you don't need to use an expect() whenever you use the variable.
 // Also, according to the docs, vecs of size 0 don't allocate any memory anyways, so it literally doesn't matter.
@@ -49,16 +74,28 @@ static CONFIG: OnceCell<Config> = OnceCell::const_new();
 
 const DB_URL: &str = "sqlite://sqlite.db";
 
+// Run with --test (or TEST_MODE=1) to keep experiments out of the real database and global commands.
+const TEST_DB_URL: &str = "sqlite://test.db";
+static TEST_MODE: AtomicBool = AtomicBool::new(false);
+
+fn test_mode() -> bool {
+    TEST_MODE.load(Ordering::Relaxed)
+}
+
 static DB: OnceCell<SqlitePool> = OnceCell::const_new();
 
 static HYPER: OnceCell<hyper::Client<HttpsConnector<HttpConnector>>> = OnceCell::const_new();
 
 static KEY: OnceCell<Box<str>> = OnceCell::const_new();
 
+// Sent with every request made directly through HYPER, since YouTube serves different (or no) content to unidentified clients.
+static SCRAPE_USER_AGENT: OnceCell<HeaderValue> = OnceCell::const_new();
+
 static YOUTUBE: OnceCell<RateLimiter<YouTube<HttpsConnector<HttpConnector>>>> =
     OnceCell::const_new();
 
 // 1 day / 10,000 (which is the rate limit)
+// This is the default, and also the fastest /setpollrate will allow.
 const TIME_PER_REQUEST: Duration = Duration::from_millis(
     1000 // 1000 milliseconds per second
     * 60 // 60 seconds per minute
@@ -67,6 +104,118 @@ const TIME_PER_REQUEST: Duration = Duration::from_millis(
     / 10000, // 10000 requests per day
 );
 
+const COMMAND_REGISTRATION_ATTEMPTS: u32 = 5;
+
+// Discord can rate limit or transiently fail this (e.g. during a mass restart),
+// which shouldn't take the whole bot down before the update loop even starts.
+async fn register_commands(http: &Http) {
+    let mut backoff = Duration::from_secs(1);
+    for attempt in 1..=COMMAND_REGISTRATION_ATTEMPTS {
+        // Guild commands update instantly, while global ones can take a while to show up.
+        let result = if test_mode() {
+            test_guild()
+                .set_commands(http, create_commands())
+                .await
+                .map(|_| ())
+        } else {
+            Command::set_global_commands(http, create_commands())
+                .await
+                .map(|_| ())
+        };
+        match result {
+            Ok(_) => return,
+            Err(why) if attempt < COMMAND_REGISTRATION_ATTEMPTS => {
+                log_error(format!(
+                    "Failed to set application commands (attempt {}/{}): {}\n\tRetrying in {:?}...",
+                    attempt, COMMAND_REGISTRATION_ATTEMPTS, why, backoff
+                ));
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(why) => log_error(format!(
+                "\tERROR: Giving up on setting application commands after {} attempts: {}",
+                attempt, why
+            )),
+        }
+    }
+}
+
+// Only used in test mode, which registers commands to this guild instead of globally.
+fn test_guild() -> GuildId {
+    let id = CONFIG
+        .get()
+        .unwrap()
+        .get_int("test_guild")
+        .expect("Failed to parse test_guild as an integer");
+    assert!(
+        id > 0,
+        "Test mode needs test_guild set to the id of your test server"
+    );
+    GuildId::new(id as u64)
+}
+
+// Resolving each url is a page scrape, so space them out rather than hitting YouTube all at once.
+const AUTOSUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+async fn autosubscribe(channel_id: ChannelId, urls: Vec<String>) {
+    for url in urls {
+        match get_upload_playlist_id(url.clone()).await {
+            Ok(playlist_id) => match add_channel(&playlist_id, channel_id, None).await {
+                Ok(r) if r.rows_affected() > 0 => log_info(format!(
+                    "Autosubscribed channel {} to uploads playlist {} ({})",
+                    channel_id, playlist_id, url
+                )),
+                Ok(_) => (),
+                Err(e) => log_error(format!("add_channel in autosubscribe:\t{}", e)),
+            },
+            Err(e) => log_error(format!(
+                "get_upload_playlist_id in autosubscribe:\tFailed to resolve {}: {:?}",
+                url, e
+            )),
+        }
+        tokio::time::sleep(AUTOSUBSCRIBE_DELAY).await;
+    }
+}
+
+// Fills in guild_id for subscriptions that were made before it was stored.
+// DM channels never get one, so those are looked up again on every startup, but that's just one request each.
+async fn backfill_guild_ids(http: Arc<Http>) {
+    let channels = match get_channels_without_guild().await {
+        Ok(v) => v,
+        Err(e) => {
+            log_error(format!(
+                "get_channels_without_guild in backfill_guild_ids:\t{}",
+                e
+            ));
+            return;
+        }
+    };
+    let mut filled = 0;
+    for channel_id in channels {
+        match channel_id.to_channel(&http).await {
+            Ok(Channel::Guild(channel)) => {
+                match set_channel_guild(channel_id, channel.guild_id).await {
+                    Ok(_) => filled += 1,
+                    Err(e) => log_error(format!("set_channel_guild in backfill_guild_ids:\t{}", e)),
+                }
+            }
+            Ok(_) => (),
+            Err(e) => log_error(format!(
+                "to_channel in backfill_guild_ids:\tFailed to look up channel {}: {}",
+                channel_id, e
+            )),
+        }
+    }
+    if filled > 0 {
+        log_info(format!(
+            "Filled in the server for {} channels' subscriptions",
+            filled
+        ));
+    }
+}
+
+static UPDATE_LOOP_STARTED: AtomicBool = AtomicBool::new(false);
+
 struct Handler;
 
 #[async_trait]
@@ -76,27 +225,111 @@ impl EventHandler for Handler {
             Interaction::Command(command) => {
                 // Commands are implemented in src/commands.rs
                 if let Err(why) = handle_command(ctx, command).await {
-                    println!("Cannot respond to slash command: {}", why);
+                    log_error(format!("Cannot respond to slash command: {}", why));
                 };
             }
             Interaction::Component(component) => {
                 // Components are implemented in src/components.rs
                 if let Err(why) = handle_component(ctx, component).await {
-                    println!("Cannot respond to message component: {}", why);
+                    log_error(format!("Cannot respond to message component: {}", why));
                 }
             }
-            _ => println!("Unimplemented interaction: {:?}", interaction.kind()),
+            _ => log_error(format!(
+                "Unimplemented interaction: {:?}",
+                interaction.kind()
+            )),
+        }
+    }
+
+    // serenity reconnects on its own, so these are just here to leave a trace of it in the logs.
+    async fn resume(&self, ctx: Context, _: ResumedEvent) {
+        log_info(format!("Resumed session on shard {}", ctx.shard_id.get()));
+    }
+
+    async fn shard_stage_update(&self, _: Context, event: ShardStageUpdateEvent) {
+        let shard = event.shard_id.get();
+        record_shard_connected(shard, event.new == ConnectionStage::Connected);
+        let mut disconnected_at = DISCONNECTED_AT.lock().unwrap();
+        if event.old == ConnectionStage::Connected {
+            disconnected_at.insert(shard, Instant::now());
+            log_info(format!(
+                "Shard {} lost its connection ({})",
+                shard, event.new
+            ));
+        } else if event.new == ConnectionStage::Connected {
+            match disconnected_at.remove(&shard) {
+                Some(since) => log_info(format!(
+                    "Shard {} reconnected after {:?}",
+                    shard,
+                    since.elapsed()
+                )),
+                None => log_info(format!("Shard {} connected", shard)),
+            }
+        } else {
+            log_info(format!("Shard {} is {}", shard, event.new));
         }
     }
 
     async fn ready(&self, ctx: Context, ready: Ready) {
-        println!("{} is connected!", ready.user.name);
+        log_info(format!(
+            "{} is connected on shard {}!",
+            ready.user.name,
+            ctx.shard_id.get()
+        ));
+
+        // This fires once per shard, but commands are global and the update loop only needs ctx.http,
+        // which isn't tied to any shard. So only the first shard to connect does either of these.
+        if UPDATE_LOOP_STARTED.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        register_commands(&ctx.http).await;
+
+        tokio::spawn(backfill_guild_ids(ctx.http.clone()));
+        tokio::spawn(update_loop(&YoutubeApi, ctx.http));
+    }
+}
 
-        Command::set_global_commands(&ctx.http, create_commands())
-            .await
-            .expect("Failed to set application commands");
+// When each shard last dropped out of ConnectionStage::Connected, to report how long reconnecting took.
+static DISCONNECTED_AT: std::sync::Mutex<BTreeMap<u32, Instant>> =
+    std::sync::Mutex::new(BTreeMap::new());
+
+const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36";
+
+// 0 waits forever.
+fn shutdown_timeout() -> Duration {
+    Duration::from_secs(
+        CONFIG
+            .get()
+            .unwrap()
+            .get_int("shutdown_timeout")
+            .expect("Failed to parse shutdown_timeout as an integer")
+            .try_into()
+            .expect("shutdown_timeout must not be negative"),
+    )
+}
 
-        tokio::spawn(update_loop(ctx.http));
+// Discord ids are big enough that people often quote them, so strings are accepted as well as integers.
+// Every bad entry is reported at once, instead of just the first one.
+fn parse_admins(values: Vec<Value>) -> Result<Vec<UserId>, Vec<String>> {
+    let mut admins = vec![];
+    let mut malformed = vec![];
+    for value in values {
+        let id = match &value.kind {
+            ValueKind::U64(id) => Some(*id),
+            ValueKind::I64(id) => u64::try_from(*id).ok(),
+            ValueKind::String(s) => u64::from_str(s.trim()).ok(),
+            _ => None,
+        };
+        match id {
+            Some(id) if id != 0 => admins.push(UserId::new(id)),
+            _ => malformed.push(value.to_string()),
+        }
+    }
+    if malformed.is_empty() {
+        Ok(admins)
+    } else {
+        Err(malformed)
     }
 }
 
@@ -104,55 +337,159 @@ fn build_config() -> Result<Config, ConfigError> {
     Config::builder()
         .add_source(File::with_name("config"))
         .set_default("admins", Vec::<u64>::new())?
+        .set_default("user_agent", DEFAULT_USER_AGENT)?
+        .set_default("shards", 1)?
+        .set_default("max_videos_per_cycle", 10)?
+        .set_default("resync_attempts", 1000)?
+        .set_default("metrics_address", "")?
+        .set_default("startup_delay", 0)?
+        .set_default("prune_deleted_channels", true)?
+        .set_default("playlist_id_strategy", "scrape")?
+        .set_default("max_subscriptions_per_guild", 0)?
+        .set_default("api_timeout", 30)?
+        .set_default("log_dedup_window", 600)?
+        .set_default("shutdown_timeout", 30)?
+        .set_default("poll_order", "last_polled")?
+        .set_default("cache_capacity", 10000)?
+        .set_default("cache_ttl", 604800)?
+        .set_default("test_guild", 0)?
+        .set_default("title_dedup_window", 86400)?
+        .set_default("digest_time", "09:00")?
+        .set_default("published_at_source", "video")?
+        .set_default("report_channel", 0)?
+        .set_default("maintenance_mode", "hold")?
+        .set_default("autosubscribe.urls", Vec::<String>::new())?
         .set_override_option("token", env::var("DISCORD_TOKEN").ok())?
         .set_override_option("key", env::var("YOUTUBE_KEY").ok())?
         .build()
 }
 
+// Any public channel with regular uploads works. Pass a different url after --selftest to use that instead.
+const SELFTEST_CHANNEL_URL: &str = "https://www.youtube.com/@YouTube";
+
+// How many of the newest uploads --selftest fetches details for.
+const SELFTEST_VIDEOS: usize = 5;
+
+// Runs the same steps as subscribing and then polling a channel, printing what each one returned.
+// Nothing is sent to Discord or written to the database. Returns whether every step succeeded.
+async fn run_selftest(channel_url: &str) -> bool {
+    log_info(format!("Self-test using {}", channel_url));
+
+    let playlist_id = match get_upload_playlist_id(channel_url).await {
+        Ok(id) => {
+            log_info(format!("\tResolved uploads playlist: {}", id));
+            id
+        }
+        Err(e) => {
+            log_info(format!("\tFAILED to resolve uploads playlist: {:?}", e));
+            return false;
+        }
+    };
+
+    let mut videos = match get_uploads_from_playlist(&playlist_id).await {
+        Ok(v) => {
+            log_info(format!("\tFetched {} uploads", v.len()));
+            v
+        }
+        Err(e) => {
+            log_info(format!("\tFAILED to fetch uploads: {:?}", e));
+            return false;
+        }
+    };
+    if videos.is_empty() {
+        log_info(
+            "\tFAILED: the playlist has no uploads, so there is nothing to fetch details for"
+                .to_string(),
+        );
+        return false;
+    }
+
+    // Newest first, which is how the API returns them, but don't count on it
+    videos.sort_by_key(|v| std::cmp::Reverse(v.published_at));
+    videos.truncate(SELFTEST_VIDEOS);
+    match get_videos_extras(&videos).await {
+        Ok(extras) => {
+            log_info(format!("\tFetched details for {} videos:", extras.len()));
+            for (video, extras) in videos.iter().zip(extras.iter()) {
+                log_info(format!(
+                    "\t\t{} ({}) {:?}, {}, {}{}",
+                    video.id,
+                    video.published_at,
+                    extras.title.as_deref().unwrap_or("<no title>"),
+                    extras.duration,
+                    extras.privacy_status,
+                    if extras.age_restricted {
+                        ", age-restricted"
+                    } else {
+                        ""
+                    }
+                ));
+            }
+            true
+        }
+        Err(e) => {
+            log_info(format!("\tFAILED to fetch video details: {:?}", e));
+            false
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), sqlx::Error> {
-    // based on https://tms-dev-blog.com/rust-sqlx-basics-with-sqlite/#Creating_an_SQLite_database, accessed 2024-08-20.
-    if !Sqlite::database_exists(DB_URL).await? {
-        Sqlite::create_database(DB_URL).await?;
-        let db = SqlitePool::connect(DB_URL).await?;
-        query(
-            "CREATE TABLE IF NOT EXISTS channels (
-                playlist_id TEXT NOT NULL,
-                channel_id INTEGER NOT NULL,
-                most_recent TEXT NOT NULL CHECK ( DATETIME(most_recent) IS most_recent ),
-                PRIMARY KEY (playlist_id, channel_id)
-            ) STRICT",
-        )
-        .execute(&db)
-        .await?;
-        DB.set(db)
+    // Checks that the API key and network work, then exits without touching Discord or the database.
+    let selftest_url = env::args()
+        .skip_while(|arg| arg != "--selftest")
+        .nth(1)
+        .filter(|arg| !arg.starts_with("--"));
+    let selftest = env::args().any(|arg| arg == "--selftest");
+
+    TEST_MODE.store(
+        env::args().any(|arg| arg == "--test")
+            || env::var("TEST_MODE").is_ok_and(|v| !v.is_empty() && v != "0"),
+        Ordering::Relaxed,
+    );
+    let db_url = if test_mode() {
+        log_info(format!("Running in test mode, using {}", TEST_DB_URL));
+        TEST_DB_URL
     } else {
-        DB.set(SqlitePool::connect(DB_URL).await?)
+        DB_URL
+    };
+
+    if !selftest {
+        // based on https://tms-dev-blog.com/rust-sqlx-basics-with-sqlite/#Creating_an_SQLite_database, accessed 2024-08-20.
+        if !Sqlite::database_exists(db_url).await? {
+            Sqlite::create_database(db_url).await?;
+        }
+        let db = SqlitePool::connect(db_url).await?;
+        update_db_schema(&db).await?;
+        DB.set(db).expect("Somehow a race condition for DB???");
+
+        let reconciled = reconcile_pending_sends().await?;
+        if reconciled > 0 {
+            log_info(format!(
+                "Advanced {} subscription cursors left behind by an interrupted send.",
+                reconciled
+            ));
+        }
     }
-    .expect("Somehow a race condition for DB???");
 
-    // Configure the client with your Discord bot token in your `config` file.
     let config = build_config().expect("Config failed");
 
-    let token = config.get_string("token").expect("Token not found. Either:\n
-                                                                    - put it in the `config` file (token = \"token\")\n
-                                                                    - set environment variable DISCORD_TOKEN.\n");
-
-    let admins = config
-        .get_array("admins")
-        .expect("Somehow failed to get admin list even though there is a default value??")
-        .iter()
-        .map(|val| {
-            UserId::new(
-                val.clone()
-                    .into_uint()
-                    .expect("Failed to parse admin list entry into UserId"),
-            )
-        })
-        .collect::<Vec<UserId>>();
+    // Ignoring the bad entries could leave the list empty, which would let anyone use admin commands.
+    let admins = match parse_admins(
+        config
+            .get_array("admins")
+            .expect("Somehow failed to get admin list even though there is a default value??"),
+    ) {
+        Ok(admins) => admins,
+        Err(malformed) => panic!(
+            "Failed to parse these admin list entries into user ids: {}",
+            malformed.join(", ")
+        ),
+    };
 
     if admins.is_empty() {
-        println!("\tWARNING: No admin users specified in config file!\n\tBy default, any user will be able to shut down your bot.");
+        log_info("\tWARNING: No admin users specified in config file!\n\tBy default, any user will be able to use admin commands, such as shutting down your bot.".to_string());
     }
 
     ADMIN_USERS
@@ -166,6 +503,16 @@ async fn main() -> Result<(), sqlx::Error> {
     KEY.set(key.into_boxed_str())
         .expect("Somehow a race condition for KEY???");
 
+    let user_agent = config
+        .get_string("user_agent")
+        .expect("Failed to parse user_agent as a string");
+    SCRAPE_USER_AGENT
+        .set(
+            HeaderValue::from_str(&user_agent)
+                .expect("user_agent is not a valid HTTP header value"),
+        )
+        .expect("Somehow a race condition for SCRAPE_USER_AGENT???");
+
     HYPER
         .set(
             hyper::Client::builder().build(
@@ -188,9 +535,140 @@ async fn main() -> Result<(), sqlx::Error> {
         _ => (),
     }
 
+    // Otherwise a bad key only shows up as errors deep in the update loop.
+    log_info("Verifying YouTube API key...".to_string());
+    match verify_key().await {
+        Ok(_) => (),
+        Err(ApiError::YouTube3(google_youtube3::Error::BadRequest(e)))
+            if e.to_string().contains("keyInvalid")
+                || e.to_string().contains("API_KEY_INVALID") =>
+        {
+            panic!("YouTube API key rejected:\n{}", e)
+        }
+        // Quota errors, network hiccups, etc. don't mean the key itself is wrong.
+        Err(e) => log_info(format!(
+            "\tWARNING: Could not verify YouTube API key: {}",
+            e
+        )),
+    }
+
+    let playlist_id_strategy = config
+        .get_string("playlist_id_strategy")
+        .expect("Failed to parse playlist_id_strategy as a string");
+    if !PLAYLIST_ID_STRATEGIES.contains(&playlist_id_strategy.as_str()) {
+        panic!(
+            "Invalid playlist_id_strategy \"{}\", expected one of: {:?}",
+            playlist_id_strategy, PLAYLIST_ID_STRATEGIES
+        );
+    }
+
+    let published_at_source = config
+        .get_string("published_at_source")
+        .expect("Failed to parse published_at_source as a string");
+    if !PUBLISHED_AT_SOURCES.contains(&published_at_source.as_str()) {
+        panic!(
+            "Invalid published_at_source \"{}\", expected one of: {:?}",
+            published_at_source, PUBLISHED_AT_SOURCES
+        );
+    }
+
+    let poll_order = config
+        .get_string("poll_order")
+        .expect("Failed to parse poll_order as a string");
+    if !POLL_ORDERS.contains(&poll_order.as_str()) {
+        panic!(
+            "Invalid poll_order \"{}\", expected one of: {:?}",
+            poll_order, POLL_ORDERS
+        );
+    }
+
+    let maintenance_mode = config
+        .get_string("maintenance_mode")
+        .expect("Failed to parse maintenance_mode as a string");
+    if !MAINTENANCE_MODES.contains(&maintenance_mode.as_str()) {
+        panic!(
+            "Invalid maintenance_mode \"{}\", expected one of: {:?}",
+            maintenance_mode, MAINTENANCE_MODES
+        );
+    }
+
+    // 0 means let Discord decide how many shards to use
+    let shards = u32::try_from(
+        config
+            .get_int("shards")
+            .expect("Failed to parse shards as an integer"),
+    )
+    .expect("shards must not be negative");
+
+    let autosubscribe_urls = config
+        .get_array("autosubscribe.urls")
+        .expect("Failed to parse autosubscribe.urls as an array")
+        .into_iter()
+        .map(|val| {
+            val.into_string()
+                .expect("Failed to parse autosubscribe.urls entry as a string")
+        })
+        .collect::<Vec<String>>();
+    let autosubscribe_channel = (!autosubscribe_urls.is_empty()).then(|| {
+        let id = config
+            .get_int("autosubscribe.channel")
+            .expect("autosubscribe.urls is set, but autosubscribe.channel is missing");
+        // ChannelId::new panics on 0 with a much less helpful message, and negative ids would wrap around
+        if id <= 0 {
+            panic!(
+                "autosubscribe.channel must be a Discord channel id, not {}",
+                id
+            );
+        }
+        ChannelId::new(id as u64)
+    });
+
+    let metrics_address = config
+        .get_string("metrics_address")
+        .expect("Failed to parse metrics_address as a string");
+
     CONFIG
         .set(config)
         .expect("Somehow a race condition for CONFIG???");
+    // Fail now on a bad value, rather than in the middle of the update loop
+    max_videos_per_cycle();
+    resync_attempts();
+    startup_delay();
+    prune_deleted_channels();
+    max_subscriptions_per_guild();
+    api_timeout();
+    log_dedup_window();
+    cache_capacity();
+    cache_ttl();
+    title_dedup_window();
+    digest_time();
+    report_channel();
+    if test_mode() {
+        test_guild();
+    }
+    let shutdown_timeout = shutdown_timeout();
+
+    if selftest {
+        let passed = run_selftest(selftest_url.as_deref().unwrap_or(SELFTEST_CHANNEL_URL)).await;
+        log_info(format!(
+            "Self-test {}",
+            if passed { "passed" } else { "FAILED" }
+        ));
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
+    // Only now that CONFIG is set, since resolving channel urls reads it
+    if let Some(channel_id) = autosubscribe_channel {
+        tokio::spawn(autosubscribe(channel_id, autosubscribe_urls));
+    }
+    if !metrics_address.is_empty() {
+        tokio::spawn(serve_metrics(metrics_address));
+    }
+
+    // Configure the client with your Discord bot token in your `config` file.
+    let token = CONFIG.get().unwrap().get_string("token").expect("Token not found. Either:\n
+                                                                    - put it in the `config` file (token = \"token\")\n
+                                                                    - set environment variable DISCORD_TOKEN.\n");
 
     // Build our client.
     let mut client = serenity::Client::builder(token, GatewayIntents::empty())
@@ -212,18 +690,35 @@ async fn main() -> Result<(), sqlx::Error> {
             // I have left open the possibility of using b=false for something "softer" in case you need it.
             let b = receiver.recv().await.expect("Shutdown message pass error");
             if b {
+                // A wedged send or database retry shouldn't be able to keep the process alive forever.
+                if !shutdown_timeout.is_zero() {
+                    tokio::spawn(async move {
+                        tokio::time::sleep(shutdown_timeout).await;
+                        log_error(format!(
+                            "Still running {:?} after shutdown, exiting anyway",
+                            shutdown_timeout
+                        ));
+                        std::process::exit(1);
+                    });
+                }
                 shard_manager.shutdown_all().await;
-                println!("Shutdown shard manager");
+                log_info("Shutdown shard manager".to_string());
                 break;
             }
         }
     });
 
     // Start the client.
-    match client.start().await {
-        Err(why) => println!("Client error: {}", why),
-        Ok(_) => println!("Client shutdown cleanly"),
+    let result = match shards {
+        0 => client.start_autosharded().await,
+        1 => client.start().await,
+        n => client.start_shards(n).await,
+    };
+    match result {
+        Err(why) => log_error(format!("Client error: {}", why)),
+        Ok(_) => log_info("Client shutdown cleanly".to_string()),
     }
+    release_bot_lock().await;
 
     Ok(())
 }
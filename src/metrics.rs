@@ -0,0 +1,123 @@
+use crate::log_limit::{log_error, log_info};
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+static NOTIFICATIONS_SENT: AtomicU64 = AtomicU64::new(0);
+// Keyed by YouTube API endpoint, e.g. "playlistItems"
+static API_CALLS: Mutex<BTreeMap<&str, u64>> = Mutex::new(BTreeMap::new());
+static API_ERRORS: Mutex<BTreeMap<&str, u64>> = Mutex::new(BTreeMap::new());
+// Stored as f64 bits, since there is no AtomicF64
+static POLL_CYCLE_SECONDS: AtomicU64 = AtomicU64::new(0);
+static TRACKED_PLAYLISTS: AtomicU64 = AtomicU64::new(0);
+// Keyed by shard id
+static SHARDS_CONNECTED: Mutex<BTreeMap<u32, bool>> = Mutex::new(BTreeMap::new());
+
+pub fn record_notification_sent() {
+    NOTIFICATIONS_SENT.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_api_call<T, E>(endpoint: &'static str, result: &Result<T, E>) {
+    *API_CALLS.lock().unwrap().entry(endpoint).or_default() += 1;
+    if result.is_err() {
+        *API_ERRORS.lock().unwrap().entry(endpoint).or_default() += 1;
+    }
+}
+
+pub fn record_shard_connected(shard: u32, connected: bool) {
+    SHARDS_CONNECTED.lock().unwrap().insert(shard, connected);
+}
+
+pub fn record_poll_cycle(duration: Duration, playlists: usize) {
+    POLL_CYCLE_SECONDS.store(duration.as_secs_f64().to_bits(), Ordering::Relaxed);
+    TRACKED_PLAYLISTS.store(playlists as u64, Ordering::Relaxed);
+}
+
+// Prometheus text exposition format: https://prometheus.io/docs/instrumenting/exposition_formats/
+fn render() -> String {
+    let mut out = String::new();
+    // Writing to a String can't fail, so the results are ignored
+    let _ = writeln!(out, "# TYPE notifications_sent_total counter");
+    let _ = writeln!(
+        out,
+        "notifications_sent_total {}",
+        NOTIFICATIONS_SENT.load(Ordering::Relaxed)
+    );
+    for (name, map) in [
+        ("api_calls_total", &API_CALLS),
+        ("api_errors_total", &API_ERRORS),
+    ] {
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for (endpoint, count) in map.lock().unwrap().iter() {
+            let _ = writeln!(out, "{}{{endpoint=\"{}\"}} {}", name, endpoint, count);
+        }
+    }
+    let _ = writeln!(out, "# TYPE poll_cycle_duration_seconds gauge");
+    let _ = writeln!(
+        out,
+        "poll_cycle_duration_seconds {}",
+        f64::from_bits(POLL_CYCLE_SECONDS.load(Ordering::Relaxed))
+    );
+    let _ = writeln!(out, "# TYPE tracked_playlists gauge");
+    let _ = writeln!(
+        out,
+        "tracked_playlists {}",
+        TRACKED_PLAYLISTS.load(Ordering::Relaxed)
+    );
+    let _ = writeln!(out, "# TYPE gateway_shard_connected gauge");
+    for (shard, connected) in SHARDS_CONNECTED.lock().unwrap().iter() {
+        let _ = writeln!(
+            out,
+            "gateway_shard_connected{{shard=\"{}\"}} {}",
+            shard, *connected as u8
+        );
+    }
+    out
+}
+
+// Every request gets the metrics, whatever its path, so there's no need to actually parse it.
+async fn respond(mut stream: TcpStream) -> std::io::Result<()> {
+    let mut buf = [0; 1024];
+    let _ = stream.read(&mut buf).await?;
+    let body = render();
+    stream
+        .write_all(
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .as_bytes(),
+        )
+        .await?;
+    stream.shutdown().await
+}
+
+pub async fn serve_metrics(address: String) {
+    let listener = match TcpListener::bind(&address).await {
+        Ok(l) => l,
+        Err(e) => {
+            log_error(format!("TcpListener::bind in serve_metrics:\t{}", e));
+            return;
+        }
+    };
+    log_info(format!("Serving metrics on {}", address));
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(async move {
+                    if let Err(e) = respond(stream).await {
+                        log_error(format!("respond in serve_metrics:\t{}", e));
+                    }
+                });
+            }
+            Err(e) => log_error(format!("listener.accept in serve_metrics:\t{}", e)),
+        }
+    }
+}
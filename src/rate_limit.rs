@@ -1,25 +1,36 @@
 use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 use tokio::time::sleep;
 
 pub struct RateLimiter<T: Clone> {
-    time_per: Duration,
+    // Milliseconds, so that it can be changed at runtime with /setpollrate
+    time_per: AtomicU64,
     resource: Mutex<(Instant, T)>,
 }
 
 impl<T: Clone> RateLimiter<T> {
     pub fn new(time_per: Duration, resource: T) -> Self {
         Self {
-            time_per: time_per,
+            time_per: AtomicU64::new(time_per.as_millis() as u64),
             resource: Mutex::new((Instant::now(), resource)),
         }
     }
 
+    pub fn time_per(&self) -> Duration {
+        Duration::from_millis(self.time_per.load(Ordering::Relaxed))
+    }
+
+    pub fn set_time_per(&self, time_per: Duration) {
+        self.time_per
+            .store(time_per.as_millis() as u64, Ordering::Relaxed);
+    }
+
     pub async fn use_with<Fut: Future>(&self, f: impl FnOnce(T) -> Fut) -> <Fut as Future>::Output {
         let mut lock = self.resource.lock().await;
         let elapsed = Instant::now().duration_since(lock.0);
-        if let Some(sleep_duration) = self.time_per.checked_sub(elapsed) {
+        if let Some(sleep_duration) = self.time_per().checked_sub(elapsed) {
             sleep(sleep_duration).await;
         }
         // I tried very hard to get away without this clone, but I couldn't figure it out
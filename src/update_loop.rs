@@ -1,16 +1,41 @@
-use crate::db::{get_channels_to_send, get_playlists, update_most_recent};
+use crate::cache::BoundedMap;
+use crate::commands::MAX_MESSAGE_LENGTH;
+use crate::db::{
+    add_digest_video, add_notified_title, add_notified_video, add_pending_send,
+    delete_all_subscriptions, delete_bot_lock, delete_digest_video, delete_pending_send,
+    get_channels_to_send, get_digest_channels, get_digest_videos, get_live_subscriptions,
+    get_playlists, prune_notified_titles, prune_notified_videos, set_last_notified,
+    set_last_polled, set_live_video_id, set_subscription_setting, set_thread_id, try_take_bot_lock,
+    update_most_recent, was_notified, was_title_notified, DigestVideo, SubscriptionSettings,
+};
+use crate::log_limit::{flush_suppressed_errors, log_error, log_info};
+use crate::metrics::{record_notification_sent, record_poll_cycle};
 use crate::youtube::{
-    get_uploads_from_playlist, get_videos_extras, UploadsError, Video, VideoExtras,
+    get_live_video_id, ExtrasError, UploadsError, Video, VideoExtras, VideoStats, YoutubeClient,
 };
+use crate::{CONFIG, YOUTUBE};
 
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
-use serenity::all::{CacheHttp, ChannelId, CreateMessage, Message, MessageFlags};
+use chrono_tz::Tz;
+use google_youtube3::chrono::{DateTime, NaiveDate, NaiveTime, TimeDelta, TimeZone, Utc};
+use serenity::all::{
+    AutoArchiveDuration, CacheHttp, Channel, ChannelId, ChannelType, CreateForumPost,
+    CreateMessage, CreateThread, EditThread, ErrorResponse, GuildChannel, Http, HttpError, Message,
+    MessageFlags, MessageId, ReactionType,
+};
+use serenity::prelude::SerenityError;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
 
 struct IndexWorkunit<'a> {
     playlist_id: &'a String,
     index: usize,
     channel_id: ChannelId,
+    settings: SubscriptionSettings,
 }
 
 struct Workunit<'a> {
@@ -18,75 +43,377 @@ struct Workunit<'a> {
     video: Video,
     extras: VideoExtras,
     channel_id: ChannelId,
+    settings: SubscriptionSettings,
+    // Older videos that were held back by notify_interval, to be listed in the same message.
+    earlier: Vec<(Video, VideoExtras)>,
 }
 
-async fn process_playlists<'a>(playlists: &'a Vec<String>, http: impl CacheHttp) -> () {
-    for playlist_id in playlists.iter() {
-        let mut videos = match get_uploads_from_playlist(&playlist_id).await {
-            Ok(v) => v,
+// Playlist ids from /watch, and when to stop watching them.
+static BOOSTED: Mutex<BTreeMap<String, Instant>> = Mutex::const_new(BTreeMap::new());
 
-            Err(UploadsError::MissingContent(mc)) => {
-                println!("get_uploads_from_playlist in process_playlists:\t{:?}", mc);
-                continue;
-            }
-            Err(UploadsError::YouTube3(e)) => {
-                println!("get_uploads_from_playlist in process_playlists:\t{}", e);
-                continue;
+pub async fn boost_playlist(playlist_id: String, duration: Duration) {
+    BOOSTED
+        .lock()
+        .await
+        .insert(playlist_id, Instant::now() + duration);
+}
+
+async fn get_boosted_playlists() -> Vec<String> {
+    let mut boosted = BOOSTED.lock().await;
+    let now = Instant::now();
+    boosted.retain(|_, until| *until > now);
+    boosted.keys().cloned().collect()
+}
+
+// client is 'static because each playlist is processed in a task of its own.
+async fn process_playlists<'a>(
+    client: &'static impl YoutubeClient,
+    playlists: &'a Vec<String>,
+    http: &Arc<Http>,
+) -> () {
+    for playlist_id in playlists.iter() {
+        // This doubles as the heartbeat, so it has to happen at least once per playlist
+        // Maintenance can start partway through a cycle, and the rest of it should wait too
+        if !hold_bot_lock().await || maintenance_hold() {
+            return;
+        }
+        // Checked this often so that a digest isn't held up by a long cycle
+        if !maintenance() {
+            send_daily_digests(http).await;
+        }
+        process_playlist_isolated(client, playlist_id, http).await;
+        // Boosted playlists get checked in between every other playlist, instead of once per cycle.
+        // This doesn't use any extra quota, it just takes it from everything else for a while.
+        for boosted_id in get_boosted_playlists().await {
+            if &boosted_id != playlist_id {
+                // Each one can send notifications too, so it needs the lock just as much
+                if !hold_bot_lock().await || maintenance_hold() {
+                    return;
+                }
+                process_playlist_isolated(client, &boosted_id, http).await;
             }
-        };
+        }
+    }
+}
+
+// Runs in its own task, so that a panic while processing one playlist only loses that playlist,
+// instead of silently killing the update loop (and every notification after it).
+async fn process_playlist_isolated(
+    client: &'static impl YoutubeClient,
+    playlist_id: &String,
+    http: &Arc<Http>,
+) {
+    let task = tokio::spawn({
+        let playlist_id = playlist_id.clone();
+        let http = http.clone();
+        async move {
+            process_single_playlist(client, &playlist_id, http).await;
+        }
+    });
+    if let Err(e) = task.await {
+        log_error(format!(
+            "process_single_playlist in process_playlist_isolated:\tPanicked on playlist {}: {}",
+            playlist_id, e
+        ));
+        record_failure(playlist_id, format!("Panicked: {}", e)).await;
+    }
+}
+
+// Playlists whose most recent poll failed, with the last error and how many polls in a row have failed.
+// Only kept in memory, since it's for /failures to show what's broken right now.
+// Bounded, since playlists that are unsubscribed while failing are never cleared.
+static FAILURES: Mutex<BoundedMap<String, (String, u32)>> = Mutex::const_new(BoundedMap::new());
+
+async fn record_failure(playlist_id: &String, error: String) {
+    let mut failures = FAILURES.lock().await;
+    let count = failures.get(playlist_id).map_or(0, |(_, count)| *count);
+    failures.insert(playlist_id.clone(), (error, count + 1));
+}
+
+pub async fn get_failures() -> Vec<(String, String, u32)> {
+    FAILURES
+        .lock()
+        .await
+        .iter()
+        .map(|(playlist_id, (error, count))| (playlist_id.clone(), error.clone(), *count))
+        .collect()
+}
+
+// 0 means no limit.
+pub fn max_videos_per_cycle() -> usize {
+    usize::try_from(
+        CONFIG
+            .get()
+            .unwrap()
+            .get_int("max_videos_per_cycle")
+            .expect("Failed to parse max_videos_per_cycle as an integer"),
+    )
+    .expect("max_videos_per_cycle must not be negative")
+}
+
+// How many times resync_db retries before leaving the rest for the next startup to reconcile.
+pub fn resync_attempts() -> usize {
+    usize::try_from(
+        CONFIG
+            .get()
+            .unwrap()
+            .get_int("resync_attempts")
+            .expect("Failed to parse resync_attempts as an integer"),
+    )
+    .expect("resync_attempts must not be negative")
+}
+
+// "last_polled" checks whatever has waited longest first, which keeps the worst-case delay down.
+// "playlist_id" always checks in the same order.
+pub const POLL_ORDERS: [&str; 2] = ["last_polled", "playlist_id"];
+
+pub fn poll_order() -> String {
+    CONFIG.get().unwrap().get_string("poll_order").unwrap()
+}
+
+// How far back dedup_titles looks for a matching title.
+pub fn title_dedup_window() -> TimeDelta {
+    let seconds = CONFIG
+        .get()
+        .unwrap()
+        .get_int("title_dedup_window")
+        .expect("Failed to parse title_dedup_window as an integer");
+    assert!(seconds >= 0, "title_dedup_window must not be negative");
+    TimeDelta::seconds(seconds)
+}
+
+// Time of day that daily digests are sent, in each server's /timezone.
+pub fn digest_time() -> NaiveTime {
+    let time = CONFIG
+        .get()
+        .unwrap()
+        .get_string("digest_time")
+        .expect("Failed to parse digest_time as a string");
+    NaiveTime::parse_from_str(&time, "%H:%M")
+        .unwrap_or_else(|_| panic!("Invalid digest_time \"{}\", expected HH:MM", time))
+}
+
+pub fn startup_delay() -> Duration {
+    Duration::from_secs(
+        CONFIG
+            .get()
+            .unwrap()
+            .get_int("startup_delay")
+            .expect("Failed to parse startup_delay as an integer")
+            .try_into()
+            .expect("startup_delay must not be negative"),
+    )
+}
+
+// Set by /maintenance. While it's on, nothing is sent, and maintenance_mode decides what happens to new videos.
+static MAINTENANCE: AtomicBool = AtomicBool::new(false);
+
+pub fn maintenance() -> bool {
+    MAINTENANCE.load(Ordering::Relaxed)
+}
 
-        videos.reverse();
+pub fn set_maintenance(enabled: bool) {
+    MAINTENANCE.store(enabled, Ordering::Relaxed);
+}
+
+// "hold" stops polling altogether, so everything that came out in the meantime is sent once maintenance ends.
+// "skip" keeps polling and advances the cursors without sending, so nothing from during maintenance is ever sent.
+pub const MAINTENANCE_MODES: [&str; 2] = ["hold", "skip"];
+
+pub fn maintenance_mode() -> String {
+    CONFIG
+        .get()
+        .unwrap()
+        .get_string("maintenance_mode")
+        .unwrap()
+}
+
+pub fn maintenance_hold() -> bool {
+    maintenance() && maintenance_mode() == "hold"
+}
 
-        let mut first_index = 0;
-        let mut index_workunits: Vec<IndexWorkunit> = vec![];
-        for (i, video) in videos.iter().enumerate() {
-            let channels = match get_channels_to_send(&playlist_id, &video.published_at).await {
+// Held for a whole playlist, so that /poll can't race the update loop into sending the same notification twice.
+static PROCESSING: Mutex<()> = Mutex::const_new(());
+
+// Returns the number of notifications sent.
+pub async fn process_single_playlist(
+    client: &impl YoutubeClient,
+    playlist_id: &String,
+    http: impl CacheHttp,
+) -> usize {
+    let _guard = PROCESSING.lock().await;
+    let mut videos = match client.get_uploads_from_playlist(&playlist_id).await {
+        Ok(v) => v,
+
+        Err(UploadsError::MissingContent(mc)) => {
+            log_error(format!(
+                "get_uploads_from_playlist in process_single_playlist:\t{:?}",
+                mc
+            ));
+            record_failure(playlist_id, format!("Missing content: {:?}", mc)).await;
+            return 0;
+        }
+        Err(UploadsError::YouTube3(e)) => {
+            log_error(format!(
+                "get_uploads_from_playlist in process_single_playlist:\t{}",
+                e
+            ));
+            record_failure(playlist_id, format!("YouTube API error: {}", e)).await;
+            return 0;
+        }
+        Err(UploadsError::Timeout(d)) => {
+            log_error(format!(
+                "get_uploads_from_playlist in process_single_playlist:\tTimed out after {:?} on playlist {}",
+                d, playlist_id
+            ));
+            record_failure(playlist_id, format!("Timed out after {:?}", d)).await;
+            return 0;
+        }
+    };
+
+    if let Err(e) = set_last_polled(playlist_id, &Utc::now()).await {
+        log_error(format!(
+            "set_last_polled in process_single_playlist:\t{}",
+            e
+        ));
+    }
+
+    // A stream that's still live after maintenance is alerted then, in either mode
+    let live_sent = if maintenance() {
+        0
+    } else {
+        check_live_stream(playlist_id, &http).await
+    };
+
+    videos.reverse();
+
+    let mut first_index = 0;
+    let mut index_workunits: Vec<IndexWorkunit> = vec![];
+    for (i, video) in videos.iter().enumerate() {
+        let channels =
+            match get_channels_to_send(&playlist_id, &video.id, &video.published_at).await {
                 Ok(v) => v,
 
                 Err(e) => {
-                    println!("get_channels_to_send in process_playlists:\t{}", e);
+                    log_error(format!(
+                        "get_channels_to_send in process_single_playlist:\t{}",
+                        e
+                    ));
                     continue;
                 }
             };
 
-            if channels.len() == 0 {
-                if first_index == i {
-                    // This if statement only doesn't happen if the videos are not returned in upload order.
-                    // That should never happen, but better safe than sorry.
-                    first_index = i + 1;
-                }
-            } else {
-                for channel in channels {
-                    index_workunits.push(IndexWorkunit {
-                        playlist_id: playlist_id,
-                        index: i,
-                        channel_id: channel,
-                    })
-                }
+        if channels.len() == 0 {
+            if first_index == i {
+                // This if statement only doesn't happen if the videos are not returned in upload order.
+                // That should never happen, but better safe than sorry.
+                first_index = i + 1;
+            }
+        } else {
+            for (channel, settings) in channels {
+                index_workunits.push(IndexWorkunit {
+                    playlist_id: playlist_id,
+                    index: i,
+                    channel_id: channel,
+                    settings: settings,
+                })
             }
         }
+    }
 
-        let videos_slice = &videos[first_index..];
+    // Oldest first, so deferring the rest to the next cycle leaves each cursor right before them.
+    let last_index = match max_videos_per_cycle() {
+        0 => videos.len(),
+        max => videos.len().min(first_index + max),
+    };
+    index_workunits.retain(|iw| iw.index < last_index);
+
+    let videos_slice = &videos[first_index..last_index];
 
-        if videos_slice.len() != 0 {
-            assign_workunit_extras(videos_slice, index_workunits, first_index, &http).await;
+    let sent = if videos_slice.len() != 0 {
+        match assign_workunit_extras(client, videos_slice, index_workunits, first_index, &http)
+            .await
+        {
+            Ok(n) => n,
+            Err(e) => {
+                log_error(format!(
+                    "get_videos_extras in process_single_playlist:\t{:?}",
+                    e
+                ));
+                record_failure(playlist_id, format!("Video details: {:?}", e)).await;
+                return live_sent;
+            }
+        }
+    } else {
+        0
+    };
+    FAILURES.lock().await.remove(playlist_id);
+    live_sent + sent
+}
+
+// Streams can take a while to show up in the uploads playlist, so subscriptions with live_alerts
+// also check the channel's /live page directly. Returns the number of alerts sent.
+async fn check_live_stream(playlist_id: &String, http: &impl CacheHttp) -> usize {
+    let subscriptions = match get_live_subscriptions(playlist_id).await {
+        Ok(v) if v.is_empty() => return 0,
+        Ok(v) => v,
+        Err(e) => {
+            log_error(format!(
+                "get_live_subscriptions in check_live_stream:\t{}",
+                e
+            ));
+            return 0;
+        }
+    };
+    let live_video_id = match get_live_video_id(playlist_id).await {
+        Ok(id) => id,
+        Err(e) => {
+            log_error(format!("get_live_video_id in check_live_stream:\t{:?}", e));
+            return 0;
+        }
+    };
+
+    let mut sent = 0;
+    for (channel_id, last_live) in subscriptions {
+        // Only alert on the transition to a new stream, and forget the old one once it's over
+        if live_video_id == last_live {
+            continue;
+        }
+        if let Some(video_id) = &live_video_id {
+            if let Err(e) = send_notification(
+                channel_id,
+                "Live now",
+                CreateMessage::new().content(format!("🔴 Live now: https://youtu.be/{}", video_id)),
+                http,
+            )
+            .await
+            {
+                log_error(format!("send_notification in check_live_stream:\t{}", e));
+                continue;
+            }
+            sent += 1;
+            record_notification_sent();
+            // So the stream isn't announced again once it shows up in the uploads playlist
+            if let Err(e) = add_notified_video(playlist_id, &channel_id, video_id).await {
+                log_error(format!("add_notified_video in check_live_stream:\t{}", e));
+            }
+        }
+        if let Err(e) = set_live_video_id(playlist_id, &channel_id, live_video_id.as_deref()).await
+        {
+            log_error(format!("set_live_video_id in check_live_stream:\t{}", e));
         }
     }
+    sent
 }
 
 async fn assign_workunit_extras<'a>(
+    client: &impl YoutubeClient,
     videos: &[Video],
     index_workunits: Vec<IndexWorkunit<'a>>,
     first_index: usize,
     http: &impl CacheHttp,
-) {
-    let extras = match get_videos_extras(videos).await {
-        Ok(v) => v,
-        Err(e) => {
-            println!("get_videos_extras in assign_workunit_duration:\t{:?}", e);
-            return;
-        }
-    };
+) -> Result<usize, ExtrasError> {
+    let extras = client.get_videos_extras(videos).await?;
 
     let workunits = index_workunits
         .into_iter()
@@ -97,70 +424,809 @@ async fn assign_workunit_extras<'a>(
                 video: videos[index].clone(),
                 extras: extras[index].clone(),
                 channel_id: iw.channel_id,
+                settings: iw.settings,
+                earlier: vec![],
             }
         })
         .collect();
 
-    do_workunits(workunits, http).await
+    Ok(do_workunits(workunits, http).await)
 }
 
-async fn do_workunits<'a>(workunits: Vec<Workunit<'a>>, http: impl CacheHttp) {
-    let mut db_retries = VecDeque::new();
-    for w in workunits {
-        let msg = match w
-            .channel_id
-            .send_message(
-                &http,
-                CreateMessage::new()
-                    .content(format!(
-                        "https://youtu.be/{} `({})`",
-                        w.video.id, w.extras.duration
-                    ))
-                    .flags(MessageFlags::empty()),
+// Announcing a video that isn't public could leak it, so only do that for subscriptions that explicitly opted in.
+// Age-restricted videos are announced unless the subscription opted out.
+fn announce_allowed<'a>(w: &Workunit<'a>) -> bool {
+    if w.extras.age_restricted && w.settings.skip_age_restricted {
+        return false;
+    }
+    match w.extras.privacy_status.as_str() {
+        "public" => true,
+        "unlisted" => w.settings.unlisted_allowed,
+        _ => false,
+    }
+}
+
+// Most videos in one digest message, to stay well under Discord's message length limit.
+// Any more are left for the digest after that.
+const MAX_DIGEST_VIDEOS: usize = 20;
+
+// Subscriptions with a notify_interval get at most one message per interval.
+// Subscriptions with a batch_window hold their first video until it's been out that long, to send it along with
+// anything else that came out in the meantime.
+// Videos that are held back are dropped from this batch without advancing the cursor, so they come back next time.
+// Once they can be sent, everything that's waiting is merged into its newest video's workunit.
+fn apply_notify_intervals<'a>(workunits: Vec<Workunit<'a>>) -> Vec<Workunit<'a>> {
+    let now = Utc::now();
+    let mut result: Vec<Workunit<'a>> = vec![];
+    // Index in result of each channel's digest so far, and how many videos it has
+    let mut digests: BTreeMap<ChannelId, (usize, usize)> = BTreeMap::new();
+    // Channels whose batch_window hasn't closed yet
+    let mut batching: Vec<ChannelId> = vec![];
+    // Workunits are in upload order, so each one merged in is newer than the digest it joins.
+    for mut w in workunits {
+        if w.settings.notify_interval <= 0 && w.settings.batch_window <= 0 {
+            result.push(w);
+            continue;
+        }
+        let interval = TimeDelta::minutes(w.settings.notify_interval);
+        if w.settings.notify_interval > 0
+            && w.settings.last_notified.is_some_and(|t| now - t < interval)
+        {
+            continue;
+        }
+        if batching.contains(&w.channel_id) {
+            continue;
+        }
+        // Videos that won't be announced still need to advance the cursor, but only if that can't skip
+        // past a digest. Newer ones are picked up again once nothing is waiting.
+        if !announce_allowed(&w) {
+            if !digests.contains_key(&w.channel_id) {
+                result.push(w);
+            }
+            continue;
+        }
+        // Only the oldest waiting video decides whether the window has closed.
+        if !digests.contains_key(&w.channel_id)
+            && now - w.video.published_at < TimeDelta::minutes(w.settings.batch_window)
+        {
+            batching.push(w.channel_id);
+            continue;
+        }
+        match digests.get_mut(&w.channel_id) {
+            None => {
+                digests.insert(w.channel_id, (result.len(), 1));
+                result.push(w);
+            }
+            Some((_, count)) if *count >= MAX_DIGEST_VIDEOS => (),
+            Some((index, count)) => {
+                let previous = &mut result[*index];
+                w.earlier = std::mem::take(&mut previous.earlier);
+                w.earlier
+                    .push((previous.video.clone(), previous.extras.clone()));
+                *previous = w;
+                *count += 1;
+            }
+        }
+    }
+    result
+}
+
+fn notification_message<'a>(w: &Workunit<'a>, welcome: bool) -> CreateMessage {
+    let mut content = String::new();
+    if welcome {
+        content.push_str(&format!(
+            "You're now subscribed to **{}**! New uploads will show up here.\n",
+            w.extras.channel_title.as_deref().unwrap_or(w.playlist_id)
+        ));
+    }
+    for (video, extras) in &w.earlier {
+        content.push_str(&format!(
+            "https://youtu.be/{} `({})`\n",
+            video.id, extras.duration
+        ));
+    }
+    content.push_str(&format!(
+        "https://youtu.be/{} `({})`",
+        w.video.id, w.extras.duration
+    ));
+    if w.settings.show_stats {
+        if let Some(stats) = &w.extras.statistics {
+            content.push_str(&format_stats(stats));
+        }
+    }
+    if w.settings.show_chapters {
+        content.push_str(&format_chapters(&w.extras.chapters));
+    }
+    CreateMessage::new()
+        .content(content)
+        .flags(if w.settings.suppress_embeds {
+            MessageFlags::SUPPRESS_EMBEDS
+        } else {
+            MessageFlags::empty()
+        })
+}
+
+// For /resend. Sends the newest video this subscription would have announced again, exactly as it would have
+// looked, without touching the cursor or anything else the update loop keeps track of.
+// Returns the id of the video that was sent, or a message to show the user.
+pub async fn resend_latest(
+    client: &impl YoutubeClient,
+    playlist_id: &String,
+    channel_id: ChannelId,
+    settings: SubscriptionSettings,
+    http: impl CacheHttp,
+) -> Result<String, String> {
+    let mut videos = client
+        .get_uploads_from_playlist(playlist_id)
+        .await
+        .map_err(|e| format!("Failed to get uploads from YouTube: {:?}", e))?;
+    if videos.is_empty() {
+        return Err(format!("Uploads playlist {} is empty.", playlist_id));
+    }
+    videos.sort_by_key(|v| std::cmp::Reverse(v.published_at));
+    let extras = client
+        .get_videos_extras(&videos)
+        .await
+        .map_err(|e| format!("Failed to get video details from YouTube: {:?}", e))?;
+
+    let w = videos
+        .into_iter()
+        .zip(extras)
+        .map(|(video, extras)| Workunit {
+            playlist_id,
+            video,
+            extras,
+            channel_id,
+            settings: settings.clone(),
+            earlier: vec![],
+        })
+        .find(announce_allowed)
+        .ok_or_else(|| {
+            format!(
+                "None of the latest uploads in {} would be announced here.",
+                playlist_id
             )
+        })?;
+
+    let target = if w.settings.use_thread {
+        get_notification_thread(&w, w.settings.thread_id, &http)
             .await
+            .map_err(|e| format!("Failed to find or create this creator's thread: {}", e))?
+    } else {
+        w.channel_id
+    };
+    send_notification(
+        target,
+        w.extras.title.as_ref().unwrap_or(&w.video.id),
+        notification_message(&w, false),
+        &http,
+    )
+    .await
+    .map_err(|e| format!("Failed to send the notification: {}", e))?
+    .react(&w.settings.reactions, &http)
+    .await;
+    record_notification_sent();
+    Ok(w.video.id)
+}
+
+async fn do_workunits<'a>(workunits: Vec<Workunit<'a>>, http: impl CacheHttp) -> usize {
+    // Anything held back for a digest would otherwise come back once maintenance is over
+    let workunits = if maintenance() {
+        workunits
+    } else {
+        apply_notify_intervals(workunits)
+    };
+    let mut sent = 0;
+    let mut db_retries = VecDeque::new();
+    let mut welcomed = vec![];
+    let mut deleted = vec![];
+    // Threads created during this batch, which aren't in anyone's settings yet
+    let mut threads: BTreeMap<ChannelId, ChannelId> = BTreeMap::new();
+    for w in workunits {
+        if deleted.contains(&w.channel_id) {
+            continue;
+        }
+        // Left for after maintenance, cursor and all, if it was turned on partway through this playlist
+        if maintenance_hold() {
+            continue;
+        }
+        if !announce_allowed(&w) || maintenance() {
+            // Still advance the cursor and mark it as seen, so it isn't reconsidered every cycle
+            if let Err(e) =
+                update_most_recent(w.playlist_id, &w.channel_id, &w.video.published_at).await
+            {
+                log_error(format!(
+                    "update_most_recent (skipped video) in do_workunits:\t{}",
+                    e
+                ));
+            }
+            if let Err(e) = add_notified_video(w.playlist_id, &w.channel_id, &w.video.id).await {
+                log_error(format!(
+                    "add_notified_video (skipped video) in do_workunits:\t{}",
+                    e
+                ));
+            }
+            continue;
+        }
+
+        // The cursor alone can't catch a video that reappears with a newer timestamp, e.g. after being re-added.
+        match was_notified(w.playlist_id, &w.channel_id, &w.video.id).await {
+            Ok(false) => (),
+            Ok(true) => {
+                log_info(format!(
+                    "Skipping video {} in channel {}, which was already notified",
+                    w.video.id, w.channel_id
+                ));
+                if let Err(e) =
+                    update_most_recent(w.playlist_id, &w.channel_id, &w.video.published_at).await
+                {
+                    log_error(format!(
+                        "update_most_recent (repeat video) in do_workunits:\t{}",
+                        e
+                    ));
+                }
+                continue;
+            }
+            Err(e) => log_error(format!("was_notified in do_workunits:\t{}", e)),
+        }
+
+        if w.settings.dedup_titles {
+            if let Some(title) = &w.extras.title {
+                match was_title_notified(
+                    w.playlist_id,
+                    &w.channel_id,
+                    title,
+                    &(Utc::now() - title_dedup_window()),
+                )
+                .await
+                {
+                    Ok(false) => (),
+                    Ok(true) => {
+                        log_info(format!(
+                            "Skipping video {} in channel {}, whose title was recently notified",
+                            w.video.id, w.channel_id
+                        ));
+                        if let Err(e) =
+                            update_most_recent(w.playlist_id, &w.channel_id, &w.video.published_at)
+                                .await
+                        {
+                            log_error(format!(
+                                "update_most_recent (repeat title) in do_workunits:\t{}",
+                                e
+                            ));
+                        }
+                        if let Err(e) =
+                            add_notified_video(w.playlist_id, &w.channel_id, &w.video.id).await
+                        {
+                            log_error(format!(
+                                "add_notified_video (repeat title) in do_workunits:\t{}",
+                                e
+                            ));
+                        }
+                        continue;
+                    }
+                    Err(e) => log_error(format!("was_title_notified in do_workunits:\t{}", e)),
+                }
+            }
+        }
+
+        if w.settings.daily_digest {
+            queue_for_digest(&w).await;
+            continue;
+        }
+
+        // Journal the send first, so that a crash before the cursor advances doesn't re-send it after a restart.
+        if let Err(e) = add_pending_send(
+            w.playlist_id,
+            &w.channel_id,
+            &w.video.id,
+            &w.video.published_at,
+        )
+        .await
+        {
+            log_error(format!("add_pending_send in do_workunits:\t{}", e));
+            continue;
+        }
+
+        // Settings were read before any of these were sent, so remember who has already been welcomed.
+        let welcome = w.settings.awaiting_first && !welcomed.contains(&w.channel_id);
+
+        let target = if w.settings.use_thread {
+            let known = threads.get(&w.channel_id).copied().or(w.settings.thread_id);
+            match get_notification_thread(&w, known, &http).await {
+                Ok(thread_id) => {
+                    threads.insert(w.channel_id, thread_id);
+                    thread_id
+                }
+                Err(e) => {
+                    log_error(format!("get_notification_thread in do_workunits:\t{}", e));
+                    clear_pending_send(&w).await;
+                    continue;
+                }
+            }
+        } else {
+            w.channel_id
+        };
+
+        let msg = match send_notification(
+            target,
+            w.extras.title.as_ref().unwrap_or(&w.video.id),
+            notification_message(&w, welcome),
+            &http,
+        )
+        .await
         {
             Err(e) => {
-                println!("send_message in do_workunits:\t{}", e);
+                log_error(format!("send_notification in do_workunits:\t{}", e));
+                clear_pending_send(&w).await;
+                // A missing thread doesn't mean the channel is gone too
+                if target == w.channel_id && is_unknown_channel(&e) && prune_deleted_channels() {
+                    log_info(format!(
+                        "Channel {} no longer exists, deleting all of its subscriptions",
+                        w.channel_id
+                    ));
+                    match delete_all_subscriptions(w.channel_id).await {
+                        Ok(_) => deleted.push(w.channel_id),
+                        Err(e) => {
+                            log_error(format!("delete_all_subscriptions in do_workunits:\t{}", e))
+                        }
+                    }
+                }
                 continue;
             }
             Ok(msg) => msg,
         };
+        sent += 1;
+        record_notification_sent();
+        msg.react(&w.settings.reactions, &http).await;
+        if welcome {
+            welcomed.push(w.channel_id);
+            if let Err(e) =
+                set_subscription_setting(w.playlist_id, w.channel_id, "awaiting_first", false).await
+            {
+                log_error(format!("set_subscription_setting in do_workunits:\t{}", e));
+            }
+        }
+        // Collected first, since holding these iterators across an await makes the future not Send
+        let video_ids: Vec<&String> = w
+            .earlier
+            .iter()
+            .map(|(v, _)| &v.id)
+            .chain([&w.video.id])
+            .collect();
+        for video_id in video_ids {
+            if let Err(e) = add_notified_video(w.playlist_id, &w.channel_id, video_id).await {
+                log_error(format!("add_notified_video in do_workunits:\t{}", e));
+            }
+        }
+        if w.settings.dedup_titles {
+            let titles: Vec<&String> = w
+                .earlier
+                .iter()
+                .map(|(_, e)| &e.title)
+                .chain([&w.extras.title])
+                .flatten()
+                .collect();
+            for title in titles {
+                if let Err(e) = add_notified_title(w.playlist_id, &w.channel_id, title).await {
+                    log_error(format!("add_notified_title in do_workunits:\t{}", e));
+                }
+            }
+        }
+        if w.settings.notify_interval > 0 {
+            if let Err(e) = set_last_notified(w.playlist_id, &w.channel_id, &Utc::now()).await {
+                log_error(format!("set_last_notified in do_workunits:\t{}", e));
+            }
+        }
 
         update_db_entry(&mut db_retries, w, msg, &http).await;
     }
 
-    resync_db(db_retries).await
+    resync_db(db_retries).await;
+    sent
+}
+
+// Queued before the cursor moves, so a crash in between only means queueing it again, which is a no-op.
+async fn queue_for_digest<'a>(w: &Workunit<'a>) {
+    let creator = w.extras.channel_title.as_deref().unwrap_or(w.playlist_id);
+    // Collected first, same as in do_workunits, so this future stays Send
+    let videos: Vec<(&Video, &VideoExtras)> = w
+        .earlier
+        .iter()
+        .map(|earlier| (&earlier.0, &earlier.1))
+        .chain([(&w.video, &w.extras)])
+        .collect();
+    for (video, extras) in videos {
+        let title = extras.title.as_ref().unwrap_or(&video.id);
+        if let Err(e) =
+            add_digest_video(w.playlist_id, &w.channel_id, &video.id, creator, title).await
+        {
+            log_error(format!("add_digest_video in queue_for_digest:\t{}", e));
+            return;
+        }
+        if let Err(e) = add_notified_video(w.playlist_id, &w.channel_id, &video.id).await {
+            log_error(format!("add_notified_video in queue_for_digest:\t{}", e));
+        }
+        if w.settings.dedup_titles {
+            if let Some(title) = &extras.title {
+                if let Err(e) = add_notified_title(w.playlist_id, &w.channel_id, title).await {
+                    log_error(format!("add_notified_title in queue_for_digest:\t{}", e));
+                }
+            }
+        }
+    }
+    if let Err(e) = update_most_recent(w.playlist_id, &w.channel_id, &w.video.published_at).await {
+        log_error(format!("update_most_recent in queue_for_digest:\t{}", e));
+    }
+}
+
+// The most recent digest_time in timezone that isn't in the future.
+fn last_digest_time(now: DateTime<Utc>, timezone: Tz) -> DateTime<Utc> {
+    let local = now.with_timezone(&timezone).date_naive();
+    let time = digest_time();
+    // On a day where daylight saving time skips over digest_time, send it an hour later instead
+    let at = |date: NaiveDate| {
+        let naive = date.and_time(time);
+        timezone
+            .from_local_datetime(&naive)
+            .earliest()
+            .or_else(|| {
+                timezone
+                    .from_local_datetime(&(naive + TimeDelta::hours(1)))
+                    .earliest()
+            })
+            .map_or(now, |t| t.with_timezone(&Utc))
+    };
+    let today = at(local);
+    if today <= now {
+        today
+    } else {
+        at(local.pred_opt().unwrap_or(local))
+    }
+}
+
+const DIGEST_TITLE_LENGTH: usize = 100;
+
+fn digest_line(video: &DigestVideo) -> String {
+    let title: String = video.title.chars().take(DIGEST_TITLE_LENGTH).collect();
+    // Angle brackets stop Discord from previewing every link in the list
+    format!("- {} <https://youtu.be/{}>\n", title, video.video_id)
+}
+
+// Sends each channel everything that was queued before its most recent digest_time, grouped by creator.
+// Long digests are split over several messages, and each video is only dropped from the queue once the
+// message it's in has been sent.
+async fn send_daily_digests(http: &Arc<Http>) {
+    let channels = match get_digest_channels().await {
+        Ok(v) => v,
+        Err(e) => {
+            log_error(format!("get_digest_channels in send_daily_digests:\t{}", e));
+            return;
+        }
+    };
+    let now = Utc::now();
+    for (channel_id, timezone, oldest) in channels {
+        let timezone = timezone.and_then(|t| t.parse().ok()).unwrap_or(Tz::UTC);
+        let due = last_digest_time(now, timezone);
+        if oldest >= due {
+            continue;
+        }
+        let videos = match get_digest_videos(channel_id, &due).await {
+            Ok(v) => v,
+            Err(e) => {
+                log_error(format!("get_digest_videos in send_daily_digests:\t{}", e));
+                continue;
+            }
+        };
+
+        let mut messages: Vec<(String, Vec<&String>)> = vec![];
+        let mut content = format!(
+            "📰 **Daily digest:** {} new video{}\n",
+            videos.len(),
+            if videos.len() == 1 { "" } else { "s" }
+        );
+        let mut video_ids = vec![];
+        let mut creator: Option<&String> = None;
+        for video in &videos {
+            let mut line = String::new();
+            if creator != Some(&video.creator) {
+                line.push_str(&format!("**{}**\n", video.creator));
+                creator = Some(&video.creator);
+            }
+            line.push_str(&digest_line(video));
+            if content.len() + line.len() > MAX_MESSAGE_LENGTH && !video_ids.is_empty() {
+                messages.push((std::mem::take(&mut content), std::mem::take(&mut video_ids)));
+                // Say whose videos these are again at the top of the next message
+                line = format!("**{}**\n{}", video.creator, digest_line(video));
+            }
+            content.push_str(&line);
+            video_ids.push(&video.video_id);
+        }
+        messages.push((content, video_ids));
+
+        for (content, video_ids) in messages {
+            if let Err(e) = send_notification(
+                channel_id,
+                "Daily digest",
+                CreateMessage::new().content(content),
+                http,
+            )
+            .await
+            {
+                log_error(format!("send_notification in send_daily_digests:\t{}", e));
+                if is_unknown_channel(&e) && prune_deleted_channels() {
+                    log_info(format!(
+                        "Channel {} no longer exists, deleting all of its subscriptions",
+                        channel_id
+                    ));
+                    if let Err(e) = delete_all_subscriptions(channel_id).await {
+                        log_error(format!(
+                            "delete_all_subscriptions in send_daily_digests:\t{}",
+                            e
+                        ));
+                    }
+                }
+                break;
+            }
+            record_notification_sent();
+            for video_id in video_ids {
+                if let Err(e) = delete_digest_video(channel_id, video_id).await {
+                    log_error(format!("delete_digest_video in send_daily_digests:\t{}", e));
+                }
+            }
+        }
+    }
+}
+
+// Most chapters listed in a notification, since a long video can have dozens.
+const MAX_CHAPTERS_SHOWN: usize = 5;
+// Chapter titles are usually short, but nothing stops one from being a whole paragraph.
+const MAX_CHAPTER_TITLE_LENGTH: usize = 100;
+
+fn format_chapters(chapters: &[(String, String)]) -> String {
+    let mut out = String::new();
+    for (timestamp, title) in chapters.iter().take(MAX_CHAPTERS_SHOWN) {
+        let title: String = title.chars().take(MAX_CHAPTER_TITLE_LENGTH).collect();
+        out.push_str(&format!("\n`{}` {}", timestamp, title));
+    }
+    if chapters.len() > MAX_CHAPTERS_SHOWN {
+        out.push_str(&format!(
+            "\n...and {} more chapters",
+            chapters.len() - MAX_CHAPTERS_SHOWN
+        ));
+    }
+    out
+}
+
+// e.g. 1234567 -> "1.2M"
+fn format_count(n: u64) -> String {
+    match n {
+        0..=999 => n.to_string(),
+        1_000..=999_999 => format!("{:.1}K", n as f64 / 1e3),
+        1_000_000..=999_999_999 => format!("{:.1}M", n as f64 / 1e6),
+        _ => format!("{:.1}B", n as f64 / 1e9),
+    }
+}
+
+fn format_stats(stats: &VideoStats) -> String {
+    let mut s = String::new();
+    if let Some(views) = stats.views {
+        s.push_str(&format!(" 👁 {}", format_count(views)));
+    }
+    if let Some(likes) = stats.likes {
+        s.push_str(&format!(" 👍 {}", format_count(likes)));
+    }
+    s
+}
+
+// Discord's error code for "Unknown Channel".
+// A DM with a user who blocked the bot fails with a different code, so those subscriptions are left alone.
+const UNKNOWN_CHANNEL: isize = 10003;
+
+fn is_unknown_channel(e: &SerenityError) -> bool {
+    matches!(
+        e,
+        SerenityError::Http(HttpError::UnsuccessfulRequest(ErrorResponse { error, .. }))
+            if error.code == UNKNOWN_CHANNEL
+    )
+}
+
+pub fn prune_deleted_channels() -> bool {
+    CONFIG
+        .get()
+        .unwrap()
+        .get_bool("prune_deleted_channels")
+        .expect("Failed to parse prune_deleted_channels as a boolean")
+}
+
+// Forum channels can't receive plain messages, so each notification there gets its own post instead.
+enum Notification {
+    Message(Message),
+    ForumPost(GuildChannel),
+}
+
+impl Notification {
+    async fn delete(&self, http: impl CacheHttp) -> Result<(), SerenityError> {
+        match self {
+            Notification::Message(msg) => msg.delete(http).await,
+            Notification::ForumPost(thread) => thread.delete(http).await.map(|_| ()),
+        }
+    }
+
+    // A reaction that can't be added (e.g. a custom emoji from a server the bot isn't in) is just skipped.
+    async fn react(&self, reactions: &[String], http: impl CacheHttp) {
+        let (channel_id, message_id) = match self {
+            Notification::Message(msg) => (msg.channel_id, msg.id),
+            // A forum post's starter message has the same id as the post itself
+            Notification::ForumPost(thread) => (thread.id, MessageId::new(thread.id.get())),
+        };
+        for reaction in reactions {
+            let reaction = match ReactionType::try_from(reaction.as_str()) {
+                Ok(r) => r,
+                Err(_) => continue,
+            };
+            if let Err(e) = channel_id
+                .create_reaction(http.http(), message_id, reaction)
+                .await
+            {
+                log_error(format!("create_reaction in react:\t{}", e));
+            }
+        }
+    }
+}
+
+// Discord rejects thread names longer than this.
+const MAX_THREAD_NAME_LENGTH: usize = 100;
+
+// There's no serenity cache (the bot has no gateway intents), so without this every notification
+// would cost an extra request just to find out whether its channel is a forum.
+// A channel's kind can't change, so entries only ever go away by being evicted.
+static CHANNEL_KINDS: Mutex<BoundedMap<ChannelId, ChannelType>> =
+    Mutex::const_new(BoundedMap::new());
+
+// None if the channel couldn't be looked up, or isn't in a guild.
+async fn channel_kind(channel_id: ChannelId, http: &impl CacheHttp) -> Option<ChannelType> {
+    if let Some(kind) = CHANNEL_KINDS.lock().await.get(&channel_id) {
+        return Some(*kind);
+    }
+    match channel_id.to_channel(http).await {
+        Ok(Channel::Guild(channel)) => {
+            CHANNEL_KINDS.lock().await.insert(channel_id, channel.kind);
+            Some(channel.kind)
+        }
+        _ => None,
+    }
+}
+
+// title is only used as the name of the post in forum channels.
+async fn send_notification(
+    channel_id: ChannelId,
+    title: &str,
+    message: CreateMessage,
+    http: impl CacheHttp,
+) -> Result<Notification, SerenityError> {
+    // If this fails, the channel probably can't be sent to either, so let send_message report the error.
+    if channel_kind(channel_id, &http).await == Some(ChannelType::Forum) {
+        let name: String = title.chars().take(MAX_THREAD_NAME_LENGTH).collect();
+        channel_id
+            .create_forum_post(&http, CreateForumPost::new(name, message))
+            .await
+            .map(Notification::ForumPost)
+    } else {
+        channel_id
+            .send_message(&http, message)
+            .await
+            .map(Notification::Message)
+    }
+}
+
+// Reuses the subscription's thread if it still exists, unarchiving it if need be, and otherwise creates a new one.
+// Forum channels already give each video its own post, so those just get the channel back.
+async fn get_notification_thread<'a>(
+    w: &Workunit<'a>,
+    known: Option<ChannelId>,
+    http: &impl CacheHttp,
+) -> Result<ChannelId, SerenityError> {
+    if let Some(thread_id) = known {
+        match thread_id.to_channel(http).await {
+            Ok(Channel::Guild(thread)) => {
+                if thread.thread_metadata.is_some_and(|m| m.archived) {
+                    thread_id
+                        .edit_thread(http, EditThread::new().archived(false))
+                        .await?;
+                }
+                return Ok(thread_id);
+            }
+            // The thread was deleted, so fall through to making a new one
+            Ok(_) => (),
+            Err(e) if is_unknown_channel(&e) => (),
+            Err(e) => return Err(e),
+        }
+    }
+
+    if channel_kind(w.channel_id, http).await == Some(ChannelType::Forum) {
+        return Ok(w.channel_id);
+    }
+    let name: String = w
+        .extras
+        .channel_title
+        .as_deref()
+        .unwrap_or(w.playlist_id)
+        .chars()
+        .take(MAX_THREAD_NAME_LENGTH)
+        .collect();
+    let thread = w
+        .channel_id
+        .create_thread(
+            http,
+            CreateThread::new(name)
+                .kind(ChannelType::PublicThread)
+                .auto_archive_duration(AutoArchiveDuration::OneWeek),
+        )
+        .await?;
+    if let Err(e) = set_thread_id(w.playlist_id, &w.channel_id, Some(thread.id)).await {
+        log_error(format!("set_thread_id in get_notification_thread:\t{}", e));
+    }
+    Ok(thread.id)
 }
 
 async fn update_db_entry<'a>(
     db_retries: &mut VecDeque<Workunit<'a>>,
     w: Workunit<'a>,
-    msg: Message,
+    msg: Notification,
     http: impl CacheHttp,
 ) {
     if let Err(e) = update_most_recent(w.playlist_id, &w.channel_id, &w.video.published_at).await {
-        println!(
+        log_error(format!(
             "update_most_recent in update_db_entry:\t{}\n
             Attempting to delete message to regain consistency...",
             e
-        );
+        ));
         if let Err(e) = msg.delete(http).await {
-            println!(
+            log_error(format!(
                 "msg.delete in update_db_entry:\t{}\n
                 Uh oh. Adding to queue to be reprocessed later.",
                 e
-            );
+            ));
             db_retries.push_back(w);
+            return;
         }
     }
+    clear_pending_send(&w).await;
+}
+
+async fn clear_pending_send<'a>(w: &Workunit<'a>) {
+    // If this fails, the leftover entry is harmless: the cursor is already at least this far along.
+    if let Err(e) = delete_pending_send(w.playlist_id, &w.channel_id, &w.video.id).await {
+        log_error(format!("delete_pending_send in clear_pending_send:\t{}", e));
+    }
 }
 
 async fn resync_db<'a>(mut db_retries: VecDeque<Workunit<'a>>) {
     if db_retries.len() != 0 {
-        println!("{} DB update failures to resolve", db_retries.len());
+        log_info(format!(
+            "{} DB update failures to resolve",
+            db_retries.len()
+        ));
+        let max_failures = resync_attempts();
         let mut failure_count: usize = 0;
         loop {
+            if failure_count >= max_failures {
+                // Their pending_sends entries (journaled by do_workunits before sending) are left in place,
+                // so the next startup will still advance these cursors.
+                log_error(format!(
+                    "resync_db in do_workunits:\tgiving up on {} DB update failures after {} additional failures",
+                    db_retries.len(),
+                    failure_count
+                ));
+                for w in db_retries {
+                    log_error(format!(
+                        "resync_db in do_workunits:\tunresolved video {} in channel {} (playlist {})",
+                        w.video.id, w.channel_id, w.playlist_id
+                    ));
+                }
+                return;
+            }
             match db_retries.pop_front() {
                 None => break,
                 Some(w) => {
@@ -170,35 +1236,273 @@ async fn resync_db<'a>(mut db_retries: VecDeque<Workunit<'a>>) {
                     {
                         failure_count += 1;
                         db_retries.push_back(w);
+                    } else {
+                        clear_pending_send(&w).await;
                     }
                 }
             }
-            tokio::time::sleep(std::time::Duration::from_millis(5)).await; // at least attempt not to throttle the system
+            sleep(Duration::from_millis(5)).await; // at least attempt not to throttle the system
         }
-        println!(
+        log_info(format!(
             "All failures resolved after {} additional failures.",
             failure_count
-        );
+        ));
+    }
+}
+
+// Long enough for any playlist quirk to have settled down.
+const NOTIFIED_VIDEO_RETENTION: TimeDelta = TimeDelta::days(30);
+
+// Unique enough to tell apart two instances sharing a database.
+fn instance_id() -> &'static str {
+    static INSTANCE_ID: OnceLock<String> = OnceLock::new();
+    INSTANCE_ID.get_or_init(|| {
+        format!(
+            "{}-{}",
+            std::process::id(),
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        )
+    })
+}
+
+// If the lock holder hasn't checked a playlist in this long, it's assumed to be gone.
+// This needs to be comfortably longer than any single playlist takes to process.
+const BOT_LOCK_TIMEOUT: TimeDelta = TimeDelta::minutes(2);
+const BOT_LOCK_RETRY: Duration = Duration::from_secs(30);
+
+// So that waiting for the lock only gets logged when it changes hands, not every retry.
+static HOLDING_BOT_LOCK: AtomicBool = AtomicBool::new(false);
+
+// Makes sure two instances sharing a database (e.g. during a restart) don't both send the same notifications.
+async fn hold_bot_lock() -> bool {
+    let holding = match try_take_bot_lock(instance_id(), &(Utc::now() - BOT_LOCK_TIMEOUT)).await {
+        Ok(b) => b,
+        Err(e) => {
+            log_error(format!("try_take_bot_lock in hold_bot_lock:\t{}", e));
+            false
+        }
+    };
+    if holding != HOLDING_BOT_LOCK.swap(holding, Ordering::SeqCst) {
+        if holding {
+            log_info("Took the bot lock, sending notifications".to_string());
+        } else {
+            log_info("Another instance has the bot lock, waiting for it...".to_string());
+        }
+    }
+    holding
+}
+
+// Lets the next instance start right away, instead of waiting out BOT_LOCK_TIMEOUT.
+pub async fn release_bot_lock() {
+    if let Err(e) = delete_bot_lock(instance_id()).await {
+        log_error(format!("delete_bot_lock in release_bot_lock:\t{}", e));
     }
 }
 
+const MAX_ERROR_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
 // This function is ugly, but not terribly complicated.
 // Just lots, and lots, of error handling.
-pub async fn update_loop(http: impl CacheHttp) {
+pub async fn update_loop(client: &'static impl YoutubeClient, http: Arc<Http>) {
+    // Without sleeping on these paths, a persistently broken (or empty) database would spin this loop at full speed.
+    let mut error_backoff = Duration::from_secs(1);
+
+    let startup_delay = startup_delay();
+    if !startup_delay.is_zero() {
+        log_info(format!(
+            "Waiting {:?} before checking any playlists",
+            startup_delay
+        ));
+        sleep(startup_delay).await;
+    }
+
     loop {
-        let playlists = match get_playlists().await {
-            Ok(v) => v,
+        // Up here, so that it still happens while waiting on the bot lock or an empty database
+        flush_suppressed_errors();
+
+        if !hold_bot_lock().await {
+            sleep(BOT_LOCK_RETRY).await;
+            continue;
+        }
+
+        // Still takes the bot lock first, so another instance doesn't start sending in the meantime
+        if maintenance_hold() {
+            sleep(BOT_LOCK_RETRY).await;
+            continue;
+        }
+
+        let playlists = match get_playlists(poll_order() == "last_polled").await {
+            Ok(v) => {
+                error_backoff = Duration::from_secs(1);
+                v
+            }
 
             Err(e) => {
-                println!("get_playlists in update_loop:\t{}", e);
+                log_error(format!(
+                    "get_playlists in update_loop:\t{}\n\tRetrying in {:?}...",
+                    e, error_backoff
+                ));
+                sleep(error_backoff).await;
+                error_backoff = (error_backoff * 2).min(MAX_ERROR_BACKOFF);
                 continue;
             }
         };
 
         if playlists.len() == 0 {
+            // Nothing to rate limit us, so wait as long as a request would have.
+            sleep(YOUTUBE.get().unwrap().time_per()).await;
             continue;
         }
 
-        process_playlists(&playlists, &http).await;
+        let cycle_start = Instant::now();
+        process_playlists(client, &playlists, &http).await;
+        record_poll_cycle(cycle_start.elapsed(), playlists.len());
+
+        if let Err(e) = prune_notified_videos(&(Utc::now() - NOTIFIED_VIDEO_RETENTION)).await {
+            log_error(format!("prune_notified_videos in update_loop:\t{}", e));
+        }
+        if let Err(e) = prune_notified_titles(&(Utc::now() - title_dedup_window())).await {
+            log_error(format!("prune_notified_titles in update_loop:\t{}", e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{add_channel, get_subscription_settings, set_most_recent, update_db_schema};
+    use crate::DB;
+
+    use config::Config;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    // Only what processing a playlist reads, since the real config file isn't around for tests.
+    async fn setup() {
+        CONFIG
+            .get_or_init(|| async {
+                Config::builder()
+                    .set_default("max_videos_per_cycle", 10)
+                    .unwrap()
+                    .set_default("log_dedup_window", 0)
+                    .unwrap()
+                    .set_default("cache_capacity", 100)
+                    .unwrap()
+                    .set_default("cache_ttl", 3600)
+                    .unwrap()
+                    .set_default("title_dedup_window", 86400)
+                    .unwrap()
+                    .set_default("prune_deleted_channels", false)
+                    .unwrap()
+                    .build()
+                    .unwrap()
+            })
+            .await;
+        DB.get_or_init(|| async {
+            // Every connection to :memory: is a separate database, so there can only be one
+            let db = SqlitePoolOptions::new()
+                .max_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+                .connect("sqlite::memory:")
+                .await
+                .unwrap();
+            update_db_schema(&db).await.unwrap();
+            db
+        })
+        .await;
+    }
+
+    // Returns the same uploads every time, or times out if there aren't any.
+    struct MockClient {
+        uploads: Option<Vec<Video>>,
+        privacy_status: &'static str,
+    }
+
+    impl YoutubeClient for MockClient {
+        async fn get_uploads_from_playlist(&self, _: &str) -> Result<Vec<Video>, UploadsError> {
+            self.uploads
+                .clone()
+                .ok_or(UploadsError::Timeout(Duration::from_secs(1)))
+        }
+
+        async fn get_videos_extras(
+            &self,
+            videos: &[Video],
+        ) -> Result<Vec<VideoExtras>, ExtrasError> {
+            Ok(videos
+                .iter()
+                .map(|v| VideoExtras {
+                    duration: "1:00".to_string(),
+                    age_restricted: false,
+                    title: Some(format!("Video {}", v.id)),
+                    channel_title: Some("Creator".to_string()),
+                    privacy_status: self.privacy_status.to_string(),
+                    statistics: None,
+                    chapters: vec![],
+                })
+                .collect())
+        }
+    }
+
+    async fn cursor(playlist_id: &String, channel_id: &ChannelId) -> DateTime<Utc> {
+        get_subscription_settings(playlist_id, channel_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .0
+    }
+
+    // Private videos are never announced, so this can run all the way through without Discord.
+    #[tokio::test]
+    async fn process_single_playlist_with_mock_client() {
+        setup().await;
+        let http = Arc::new(Http::new(""));
+        let playlist_id = "UUmockmockmockmockmockmo".to_string();
+        let channel_id = ChannelId::new(1);
+        let now = Utc::now().timestamp();
+        let start = DateTime::from_timestamp(now - 7200, 0).unwrap();
+        let published_at = DateTime::from_timestamp(now - 3600, 0).unwrap();
+        add_channel(&playlist_id, channel_id, None).await.unwrap();
+        set_most_recent(&playlist_id, &channel_id, &start)
+            .await
+            .unwrap();
+
+        // A failed poll is recorded, and leaves the cursor alone
+        let failing = MockClient {
+            uploads: None,
+            privacy_status: "private",
+        };
+        assert_eq!(
+            process_single_playlist(&failing, &playlist_id, &http).await,
+            0
+        );
+        assert!(get_failures()
+            .await
+            .iter()
+            .any(|(id, _, count)| id == &playlist_id && *count == 1));
+        assert_eq!(cursor(&playlist_id, &channel_id).await, start);
+
+        // A video that can't be announced still moves the cursor past it, and clears the failure
+        let private = MockClient {
+            uploads: Some(vec![Video {
+                id: "mockvideo01".to_string(),
+                published_at,
+            }]),
+            privacy_status: "private",
+        };
+        assert_eq!(
+            process_single_playlist(&private, &playlist_id, &http).await,
+            0
+        );
+        assert!(!get_failures()
+            .await
+            .iter()
+            .any(|(id, _, _)| id == &playlist_id));
+        assert_eq!(cursor(&playlist_id, &channel_id).await, published_at);
+        assert!(
+            was_notified(&playlist_id, &channel_id, &"mockvideo01".to_string())
+                .await
+                .unwrap()
+        );
     }
 }
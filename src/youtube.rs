@@ -1,12 +1,22 @@
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
+use std::future::Future;
+use std::time::Duration;
 
-use crate::{HYPER, KEY, YOUTUBE};
+use crate::log_limit::log_error;
+use crate::metrics::record_api_call;
+use crate::{CONFIG, HYPER, KEY, SCRAPE_USER_AGENT, YOUTUBE};
 use google_youtube3::{
     api::PlaylistItemContentDetails,
     chrono::{DateTime, Utc},
     hyper,
 };
-use hyper::{body, http::uri::InvalidUri, Body, Response, StatusCode};
+use hyper::{
+    body,
+    header::{LOCATION, USER_AGENT},
+    http::uri::InvalidUri,
+    Body, Request, Response, StatusCode, Uri,
+};
+use tokio::time::timeout;
 
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -15,6 +25,8 @@ pub enum PlaylistIdError {
     Hyper(hyper::Error),
     BadStatus(StatusCode),
     BodyParseError(String),
+    NotAChannelUrl(String),
+    NotYouTube(String),
 }
 
 impl From<hyper::Error> for PlaylistIdError {
@@ -29,19 +41,273 @@ impl From<InvalidUri> for PlaylistIdError {
     }
 }
 
+// Channel pages can be addressed as /@handle, /channel/UC..., /user/name, /c/name, or just /name,
+// optionally followed by a tab (/videos, /featured, ...) and query parameters.
+// Only keep the part that identifies the channel, so that appending /search still gives a valid page.
+fn channel_page_path(path: &str) -> Option<String> {
+    let mut segments = path.split('/').filter(|s| !s.is_empty());
+    let first = segments.next()?;
+    match first {
+        "channel" | "user" | "c" => Some(format!("/{}/{}", first, segments.next()?)),
+        _ => Some(format!("/{}", first)),
+    }
+}
+
+// A channel's uploads playlist id is just its channel id with the leading "UC" swapped for "UU".
+pub fn uploads_playlist_id(channel_id: &str) -> Option<String> {
+    let valid = channel_id.len() == 24
+        && channel_id.starts_with("UC")
+        && channel_id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if valid {
+        Some(format!("UU{}", &channel_id[2..]))
+    } else {
+        None
+    }
+}
+
+// hyper's client doesn't add any default headers, so every scrape request should go through this.
+async fn scrape_get(uri: Uri) -> Result<Response<Body>, hyper::Error> {
+    let mut request = Request::new(Body::empty());
+    *request.uri_mut() = uri;
+    request
+        .headers_mut()
+        .insert(USER_AGENT, SCRAPE_USER_AGENT.get().unwrap().clone());
+    HYPER.get().unwrap().request(request).await
+}
+
+const MAX_REDIRECTS: usize = 5;
+
+// Uri only finds the host if there's a scheme, and people often leave it off
+fn with_scheme(input: &str) -> String {
+    if input.contains("://") {
+        input.to_string()
+    } else {
+        format!("https://{}", input)
+    }
+}
+
+const YOUTUBE_HOSTS: [&str; 4] = [
+    "youtube.com",
+    "www.youtube.com",
+    "m.youtube.com",
+    "youtu.be",
+];
+
+// Rejects anything that isn't YouTube before making any requests,
+// and drops the query (e.g. tracking parameters), since it never identifies a channel anyway.
+fn normalize_channel_url(input: &str) -> Result<Uri, PlaylistIdError> {
+    let input = input.trim();
+    let uri: Uri = with_scheme(input).try_into()?;
+    let host = uri.host().unwrap_or_default().to_ascii_lowercase();
+    if !YOUTUBE_HOSTS.contains(&host.as_str()) {
+        return Err(PlaylistIdError::NotYouTube(input.to_string()));
+    }
+    Ok(format!("https://www.youtube.com{}", uri.path()).try_into()?)
+}
+
+// "scrape" (free, but breaks whenever YouTube changes its pages) or "api" (1 quota unit per lookup).
+pub const PLAYLIST_ID_STRATEGIES: [&str; 2] = ["scrape", "api"];
+
+// Which timestamp a video is ordered by, and compared against each subscription's cursor.
+// "video" is contentDetails.videoPublishedAt, when the video itself was published.
+// "playlist" is snippet.publishedAt, when it was added to the uploads playlist, which can be later
+// (e.g. a video that was private for a while). That needs the snippet part, which makes each response bigger.
+pub const PUBLISHED_AT_SOURCES: [&str; 2] = ["video", "playlist"];
+
+fn published_at_source() -> String {
+    CONFIG
+        .get()
+        .unwrap()
+        .get_string("published_at_source")
+        .unwrap()
+}
+
+fn playlist_id_strategy() -> String {
+    CONFIG
+        .get()
+        .unwrap()
+        .get_string("playlist_id_strategy")
+        .unwrap()
+}
+
+pub fn api_timeout() -> Duration {
+    let seconds = CONFIG
+        .get()
+        .unwrap()
+        .get_int("api_timeout")
+        .expect("Failed to parse api_timeout as an integer");
+    assert!(seconds > 0, "api_timeout must be positive");
+    Duration::from_secs(seconds as u64)
+}
+
+#[derive(Debug)]
+pub enum ApiError {
+    YouTube3(google_youtube3::Error),
+    Timeout(Duration),
+}
+
+impl From<google_youtube3::Error> for ApiError {
+    fn from(value: google_youtube3::Error) -> Self {
+        ApiError::YouTube3(value)
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::YouTube3(e) => write!(f, "{}", e),
+            ApiError::Timeout(d) => write!(f, "YouTube didn't respond within {:?}", d),
+        }
+    }
+}
+
+// Goes inside use_with, since a request that never finishes would otherwise hold the rate limiter
+// (and with it, the update loop and every other API call) forever.
+async fn with_api_timeout<T>(
+    call: impl Future<Output = Result<T, google_youtube3::Error>>,
+) -> Result<T, ApiError> {
+    let duration = api_timeout();
+    match timeout(duration, call).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(ApiError::Timeout(duration)),
+    }
+}
+
+// The API can only find channels by handle or legacy username, not by /c/ or bare custom urls.
+async fn api_upload_playlist_id(path: &str) -> Option<String> {
+    let (param, value) = if let Some(handle) = path.strip_prefix("/@") {
+        ("forHandle", handle)
+    } else if let Some(username) = path.strip_prefix("/user/") {
+        ("forUsername", username)
+    } else {
+        return None;
+    };
+    let response = YOUTUBE
+        .get()
+        .unwrap()
+        .use_with(|yt| {
+            with_api_timeout(async move {
+                yt.channels()
+                    .list(&vec!["contentDetails".into()])
+                    .param(param, value)
+                    .param("key", KEY.get().unwrap())
+                    .doit()
+                    .await
+            })
+        })
+        .await;
+    record_api_call("channels", &response);
+    match response {
+        Ok((_, list)) => {
+            list.items?
+                .into_iter()
+                .next()?
+                .content_details?
+                .related_playlists?
+                .uploads
+        }
+        Err(e) => {
+            log_error(format!("channels().list in api_upload_playlist_id:\t{}", e));
+            None
+        }
+    }
+}
+
 pub async fn get_upload_playlist_id(
     channel_uri: impl Into<String>,
 ) -> Result<String, PlaylistIdError> {
-    let mut channel_uri = channel_uri
-        .into()
-        .replace("youtu.be", "www.youtube.com")
-        .replace("m.youtube.com", "www.youtube.com");
+    let channel_uri = normalize_channel_url(&channel_uri.into())?;
+
+    let path = channel_page_path(channel_uri.path())
+        .ok_or_else(|| PlaylistIdError::NotAChannelUrl(channel_uri.to_string()))?;
+
+    // No need to scrape anything if the channel id is already in the url
+    if let Some(channel_id) = path.strip_prefix("/channel/") {
+        return uploads_playlist_id(channel_id)
+            .ok_or_else(|| PlaylistIdError::NotAChannelUrl(channel_uri.to_string()));
+    }
+
+    if playlist_id_strategy() == "api" {
+        if let Some(playlist_id) = api_upload_playlist_id(&path).await {
+            return Ok(playlist_id);
+        }
+        // Anything the API can't look up still has a chance with the scrape
+    }
+
+    scrape_upload_playlist_id(&channel_uri, &path).await
+}
+
+// Each step get_upload_playlist_id takes, for /resolve to show.
+// Steps that don't apply to the url (or are turned off) are None.
+pub struct ResolveTrace {
+    pub normalized: Result<String, PlaylistIdError>,
+    pub channel_path: Option<String>,
+    pub fast_path: Option<String>,
+    pub api: Option<Option<String>>,
+    pub scrape: Option<Result<String, PlaylistIdError>>,
+}
+
+// Unlike get_upload_playlist_id, this doesn't stop at the first strategy that works.
+pub async fn trace_upload_playlist_id(channel_uri: &str) -> ResolveTrace {
+    let mut trace = ResolveTrace {
+        normalized: normalize_channel_url(channel_uri).map(|u| u.to_string()),
+        channel_path: None,
+        fast_path: None,
+        api: None,
+        scrape: None,
+    };
+    let channel_uri = match normalize_channel_url(channel_uri) {
+        Ok(u) => u,
+        Err(_) => return trace,
+    };
+    let path = match channel_page_path(channel_uri.path()) {
+        Some(p) => p,
+        None => return trace,
+    };
+    trace.channel_path = Some(path.clone());
+    if let Some(channel_id) = path.strip_prefix("/channel/") {
+        trace.fast_path = uploads_playlist_id(channel_id);
+    }
+    if playlist_id_strategy() == "api" {
+        trace.api = Some(api_upload_playlist_id(&path).await);
+    }
+    trace.scrape = Some(scrape_upload_playlist_id(&channel_uri, &path).await);
+    trace
+}
+
+async fn scrape_upload_playlist_id(
+    channel_uri: &Uri,
+    path: &str,
+) -> Result<String, PlaylistIdError> {
     // /search page is about 100KB smaller
-    channel_uri.push_str("/search");
+    let search_uri = format!(
+        "{}://{}{}/search",
+        channel_uri.scheme_str().unwrap_or("https"),
+        channel_uri
+            .authority()
+            .map(|a| a.as_str())
+            .unwrap_or("www.youtube.com"),
+        path
+    );
+
+    let uri = search_uri.clone().try_into()?;
 
-    let uri = channel_uri.clone().try_into()?;
+    let mut response = scrape_get(uri).await?;
 
-    let response = HYPER.get().unwrap().get(uri).await?;
+    // Legacy /user/ and /c/ urls sometimes redirect to the channel's current url
+    for _ in 0..MAX_REDIRECTS {
+        if !response.status().is_redirection() {
+            break;
+        }
+        let location = match response.headers().get(LOCATION).map(|l| l.to_str()) {
+            Some(Ok(l)) if l.starts_with('/') => format!("https://www.youtube.com{}", l),
+            Some(Ok(l)) => l.to_string(),
+            _ => break,
+        };
+        response = scrape_get(location.try_into()?).await?;
+    }
 
     let b = match response.status() {
         StatusCode::OK => Ok(response.into_body()),
@@ -50,10 +316,64 @@ pub async fn get_upload_playlist_id(
 
     let bytes = body::to_bytes(b).await?;
 
-    let prefix_bytes = *b"channel_id=";
+    playlist_id_from_page(&bytes, search_uri)
+}
+
+fn playlist_id_from_page(bytes: &[u8], search_uri: String) -> Result<String, PlaylistIdError> {
+    // If none of these work, YouTube probably changed its page layout again
+    CHANNEL_ID_PREFIXES
+        .iter()
+        .find_map(|prefix| scan_for_channel_id(bytes, prefix))
+        .and_then(|channel_id| uploads_playlist_id(&channel_id))
+        .ok_or(PlaylistIdError::BodyParseError(search_uri))
+}
+
+// A channel's /live page is the watch page of its current stream, if it has one.
+// Upcoming streams show up there too, so the page also has to say it's live right now.
+pub async fn get_live_video_id(playlist_id: &str) -> Result<Option<String>, PlaylistIdError> {
+    let channel_suffix = match playlist_id.strip_prefix("UU") {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+    let live_uri = format!("https://www.youtube.com/channel/UC{}/live", channel_suffix);
+    let response = scrape_get(live_uri.try_into()?).await?;
+
+    let b = match response.status() {
+        StatusCode::OK => Ok(response.into_body()),
+        s => Err(PlaylistIdError::BadStatus(s)),
+    }?;
+
+    let bytes = body::to_bytes(b).await?;
+
+    if find_bytes(&bytes, LIVE_NOW_MARKER).is_none() {
+        return Ok(None);
+    }
+    Ok(find_bytes(&bytes, CANONICAL_WATCH_PREFIX)
+        .map(|i| i + CANONICAL_WATCH_PREFIX.len())
+        .and_then(|start| bytes.get(start..start + 11))
+        .and_then(|id| std::str::from_utf8(id).ok())
+        .and_then(parse_video_id))
+}
+
+const LIVE_NOW_MARKER: &[u8] = b"\"isLiveNow\":true";
+const CANONICAL_WATCH_PREFIX: &[u8] =
+    b"<link rel=\"canonical\" href=\"https://www.youtube.com/watch?v=";
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+// Text that comes right before the page's channel id, in order of preference.
+// channel_id= is in the page's RSS link, but YouTube doesn't always serve it in that form.
+// externalId is from the channel's own metadata in ytInitialData, while browseId can also show up for other channels,
+// but its first occurrence is almost always the page's own channel.
+const CHANNEL_ID_PREFIXES: [&[u8]; 3] = [b"channel_id=", b"\"externalId\":\"", b"\"browseId\":\""];
+
+// Returns everything between the first occurrence of prefix and the next quote, if it looks like a channel id.
+fn scan_for_channel_id(bytes: &[u8], prefix_bytes: &[u8]) -> Option<String> {
     let mut prefix_index = 0;
     let mut buf = String::with_capacity(24);
-    for byte in bytes {
+    for &byte in bytes {
         if prefix_index >= prefix_bytes.len() {
             if byte == b'"' {
                 if buf.len() == 0 {
@@ -63,46 +383,73 @@ pub async fn get_upload_playlist_id(
                     // ending quote, break the loop, we're done!
                     break;
                 }
+            } else if buf.len() >= 24 {
+                // channel ids are exactly 24 characters, so this can't be one
+                return None;
             } else {
-                // channel Ids start "UC", and the corresponding upload playlist starts "UU"
-                if buf.len() == 1 && byte == b'C' {
-                    buf.push('U');
-                } else {
-                    buf.push(byte as char);
-                }
+                buf.push(byte as char);
             }
         } else if byte == prefix_bytes[prefix_index] {
             prefix_index += 1;
+        } else if byte == prefix_bytes[0] {
+            prefix_index = 1;
         } else {
             prefix_index = 0;
         }
     }
 
-    if buf.len() != 24 || &buf[0..2] != "UU" {
-        Err(PlaylistIdError::BodyParseError(channel_uri))
+    if buf.starts_with("UC") {
+        Some(buf)
     } else {
-        Ok(buf)
+        None
     }
 }
 
+// The cheapest possible request (1 quota unit), just to find out whether the key works at all.
+pub async fn verify_key() -> Result<(), ApiError> {
+    let response = YOUTUBE
+        .get()
+        .unwrap()
+        .use_with(|yt| {
+            with_api_timeout(async move {
+                yt.video_categories()
+                    .list(&vec!["snippet".into()])
+                    .region_code("US")
+                    .param("key", KEY.get().unwrap())
+                    .doit()
+                    .await
+            })
+        })
+        .await;
+    record_api_call("videoCategories", &response);
+    response?;
+    Ok(())
+}
+
 #[derive(Debug)]
 pub enum MissingContent {
     ContentDetails,
     VideoId,
     VideoPublishedAt,
+    SnippetPublishedAt,
     VideoDuration,
+    VideoPrivacyStatus,
 }
 
 #[derive(Debug)]
 pub enum UploadsError {
     YouTube3(google_youtube3::Error),
+    Timeout(Duration),
     // Empty(PlaylistItemListResponse),
     MissingContent(MissingContent),
 }
 
-impl From<google_youtube3::Error> for UploadsError {
-    fn from(value: google_youtube3::Error) -> Self {
-        UploadsError::YouTube3(value)
+impl From<ApiError> for UploadsError {
+    fn from(value: ApiError) -> Self {
+        match value {
+            ApiError::YouTube3(e) => UploadsError::YouTube3(e),
+            ApiError::Timeout(d) => UploadsError::Timeout(d),
+        }
     }
 }
 
@@ -132,46 +479,134 @@ impl TryFrom<PlaylistItemContentDetails> for Video {
 }
 
 pub async fn get_uploads_from_playlist(playlist_id: &str) -> Result<Vec<Video>, UploadsError> {
+    let added_to_playlist = published_at_source() == "playlist";
+    let mut parts = vec!["contentDetails".into()];
+    if added_to_playlist {
+        parts.push("snippet".into());
+    }
+    let parts = &parts;
     let response = YOUTUBE
         .get()
         .unwrap()
-        .use_with(|yt| async move {
-            yt.playlist_items()
-                .list(&vec!["contentDetails".into()])
-                .playlist_id(playlist_id)
-                .max_results(50)
-                .param("key", KEY.get().unwrap())
-                .doit()
-                .await
+        .use_with(|yt| {
+            with_api_timeout(async move {
+                yt.playlist_items()
+                    .list(parts)
+                    .playlist_id(playlist_id)
+                    .max_results(50)
+                    .param("key", KEY.get().unwrap())
+                    .doit()
+                    .await
+            })
         })
-        .await?
-        .1;
+        .await;
+    record_api_call("playlistItems", &response);
+    let response = response?.1;
 
     match response.items {
         None => Ok(vec![]),
         Some(items) => Ok(items
             .into_iter()
             .map(|pi| {
-                pi.content_details
+                let mut video: Video = pi
+                    .content_details
                     .ok_or(MissingContent::ContentDetails)?
-                    .try_into()
+                    .try_into()?;
+                if added_to_playlist {
+                    video.published_at = pi
+                        .snippet
+                        .and_then(|s| s.published_at)
+                        .ok_or(MissingContent::SnippetPublishedAt)?;
+                }
+                Ok(video)
             })
             .collect::<Result<Vec<Video>, MissingContent>>()?),
     }
 }
 
+#[derive(Default)]
+pub struct PlaylistPreview {
+    pub channel_title: Option<String>,
+    pub latest_video_id: Option<String>,
+    pub latest_video_title: Option<String>,
+}
+
+pub async fn get_playlist_preview(playlist_id: &str) -> Result<PlaylistPreview, ApiError> {
+    let response = YOUTUBE
+        .get()
+        .unwrap()
+        .use_with(|yt| {
+            with_api_timeout(async move {
+                yt.playlist_items()
+                    .list(&vec!["snippet".into()])
+                    .playlist_id(playlist_id)
+                    .max_results(1)
+                    .param("key", KEY.get().unwrap())
+                    .doit()
+                    .await
+            })
+        })
+        .await;
+    record_api_call("playlistItems", &response);
+    let response = response?.1;
+
+    // Uploads playlists are ordered newest first
+    match response
+        .items
+        .and_then(|items| items.into_iter().next())
+        .and_then(|pi| pi.snippet)
+    {
+        None => Ok(PlaylistPreview::default()),
+        Some(snippet) => Ok(PlaylistPreview {
+            channel_title: snippet.channel_title,
+            latest_video_id: snippet.resource_id.and_then(|r| r.video_id),
+            latest_video_title: snippet.title,
+        }),
+    }
+}
+
+// For subscribing from a video url. None if there's no such video.
+pub async fn get_video_channel_id(video_id: &str) -> Result<Option<String>, ApiError> {
+    let response = YOUTUBE
+        .get()
+        .unwrap()
+        .use_with(|yt| {
+            with_api_timeout(async move {
+                yt.videos()
+                    .list(&vec!["snippet".into()])
+                    .add_id(video_id)
+                    .param("key", KEY.get().unwrap())
+                    .doit()
+                    .await
+            })
+        })
+        .await;
+    record_api_call("videos", &response);
+    Ok(response?
+        .1
+        .items
+        .and_then(|items| items.into_iter().next())
+        .and_then(|v| v.snippet)
+        .and_then(|s| s.channel_id))
+}
+
 #[derive(Debug)]
 #[allow(dead_code)]
 pub enum ExtrasError {
     YouTube3(google_youtube3::Error),
+    Timeout(Duration),
     MissingContent(MissingContent),
-    Empty(Response<Body>),
+    // The ids that were asked for, since the response itself has nothing useful to log
+    NoItemsReturned(Vec<String>),
     LengthMismatch(Vec<google_youtube3::api::Video>),
 }
 
-impl From<google_youtube3::Error> for ExtrasError {
-    fn from(value: google_youtube3::Error) -> Self {
-        ExtrasError::YouTube3(value)
+impl From<ApiError> for ExtrasError {
+    fn from(value: ApiError) -> Self {
+        match value {
+            ApiError::YouTube3(e) => ExtrasError::YouTube3(e),
+            ApiError::Timeout(d) => ExtrasError::Timeout(d),
+        }
     }
 }
 
@@ -184,36 +619,210 @@ impl From<MissingContent> for ExtrasError {
 #[derive(Clone)]
 pub struct VideoExtras {
     pub duration: String,
+    pub age_restricted: bool,
+    pub title: Option<String>,
+    pub channel_title: Option<String>,
+    // "public", "unlisted", or "private"
+    pub privacy_status: String,
+    pub statistics: Option<VideoStats>,
+    // (timestamp, title) pairs from the description, in order. Empty if it doesn't have any.
+    pub chapters: Vec<(String, String)>,
+}
+
+// Either count can be hidden by the uploader.
+#[derive(Clone)]
+pub struct VideoStats {
+    pub views: Option<u64>,
+    pub likes: Option<u64>,
+}
+
+const VIDEO_ID_LENGTH: usize = 11;
+
+// For people who already know the playlist id: UU... uploads playlists, UC... channel ids (converted to
+// their uploads playlist), and PL... regular playlists. Anything else is treated as a url.
+pub fn parse_playlist_id(input: &str) -> Option<String> {
+    let input = input.trim();
+    if !input
+        .chars()
+        .all(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return None;
+    }
+    match input.get(..2) {
+        Some("UC") => uploads_playlist_id(input),
+        Some("UU") if input.len() == 24 => Some(input.to_string()),
+        Some("PL") if (18..=34).contains(&input.len()) => Some(input.to_string()),
+        _ => None,
+    }
+}
+
+fn is_video_id(s: &str) -> bool {
+    s.len() == VIDEO_ID_LENGTH
+        && s.chars()
+            .all(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// Accepts a bare video id, or any of the usual video urls:
+// youtu.be/ID, youtube.com/watch?v=ID, youtube.com/shorts/ID, youtube.com/live/ID, and youtube.com/embed/ID.
+pub fn parse_video_id(input: &str) -> Option<String> {
+    let input = input.trim();
+    if is_video_id(input) {
+        return Some(input.to_string());
+    }
+    let uri = with_scheme(input).parse::<Uri>().ok()?;
+    let host = uri
+        .host()?
+        .trim_start_matches("www.")
+        .trim_start_matches("m.");
+    let path = uri.path().trim_matches('/');
+    let candidate = match host {
+        "youtu.be" => path,
+        "youtube.com" | "music.youtube.com" => match path.split_once('/') {
+            Some(("shorts" | "live" | "embed", rest)) => rest,
+            _ if path == "watch" => uri
+                .query()?
+                .split('&')
+                .find_map(|param| param.strip_prefix("v="))?,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let candidate = candidate.split('/').next()?;
+    is_video_id(candidate).then(|| candidate.to_string())
+}
+
+// e.g. "1:23", "12:34", or "1:02:03"
+fn is_chapter_timestamp(s: &str) -> bool {
+    let mut parts = s.split(':');
+    let first = parts.next().unwrap_or_default();
+    let rest: Vec<&str> = parts.collect();
+    !first.is_empty()
+        && first.len() <= 2
+        && first.chars().all(|c| c.is_ascii_digit())
+        && (1..=2).contains(&rest.len())
+        && rest
+            .iter()
+            .all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+// Lines of the form "1:23 Title" (or "1:23 - Title"). Like YouTube itself, this only counts them as chapters
+// if the first one starts at 0:00 and there are at least 3, so that a stray timestamp in a description doesn't.
+fn parse_chapters(description: &str) -> Vec<(String, String)> {
+    let chapters: Vec<(String, String)> = description
+        .lines()
+        .filter_map(|line| {
+            let (timestamp, title) = line.trim().split_once(char::is_whitespace)?;
+            let title =
+                title.trim_start_matches(|c: char| c.is_whitespace() || "-–—|:".contains(c));
+            (is_chapter_timestamp(timestamp) && !title.is_empty())
+                .then(|| (timestamp.to_string(), title.trim_end().to_string()))
+        })
+        .collect();
+    match chapters.first() {
+        Some((first, _)) if chapters.len() >= 3 && first.chars().all(|c| c == '0' || c == ':') => {
+            chapters
+        }
+        _ => vec![],
+    }
 }
 
 pub async fn get_videos_extras(videos: &[Video]) -> Result<Vec<VideoExtras>, ExtrasError> {
+    get_extras_by_id(&videos.iter().map(|v| v.id.as_str()).collect::<Vec<&str>>()).await
+}
+
+// Everything the update loop needs from the YouTube API, so that tests can stand in for it.
+// The futures have to be Send, since each playlist is processed in a task of its own.
+pub trait YoutubeClient: Sync {
+    fn get_uploads_from_playlist(
+        &self,
+        playlist_id: &str,
+    ) -> impl Future<Output = Result<Vec<Video>, UploadsError>> + Send;
+
+    fn get_videos_extras(
+        &self,
+        videos: &[Video],
+    ) -> impl Future<Output = Result<Vec<VideoExtras>, ExtrasError>> + Send;
+}
+
+// The real API, through YOUTUBE.
+pub struct YoutubeApi;
+
+impl YoutubeClient for YoutubeApi {
+    async fn get_uploads_from_playlist(
+        &self,
+        playlist_id: &str,
+    ) -> Result<Vec<Video>, UploadsError> {
+        get_uploads_from_playlist(playlist_id).await
+    }
+
+    async fn get_videos_extras(&self, videos: &[Video]) -> Result<Vec<VideoExtras>, ExtrasError> {
+        get_videos_extras(videos).await
+    }
+}
+
+// At most 50 ids, since that's all YouTube returns in one page.
+pub async fn get_extras_by_id(video_ids: &[&str]) -> Result<Vec<VideoExtras>, ExtrasError> {
     let response = YOUTUBE
         .get()
         .unwrap()
-        .use_with(|yt| async move {
-            let mut query = yt.videos().list(&vec!["contentDetails".into()]);
-            for video in videos {
-                query = query.add_id(video.id.as_str());
-            }
-            query
-                .max_results(50)
-                .param("key", KEY.get().unwrap())
-                .doit()
-                .await
+        .use_with(|yt| {
+            with_api_timeout(async move {
+                let mut query = yt.videos().list(&vec![
+                    "contentDetails".into(),
+                    "snippet".into(),
+                    "statistics".into(),
+                    "status".into(),
+                ]);
+                for id in video_ids {
+                    query = query.add_id(id);
+                }
+                query
+                    .max_results(50)
+                    .param("key", KEY.get().unwrap())
+                    .doit()
+                    .await
+            })
         })
-        .await?;
+        .await;
+    record_api_call("videos", &response);
+    let response = response?;
 
     match response.1.items {
+        // An empty array means the same thing as a missing one, so it shouldn't count as a LengthMismatch
+        Some(v) if v.is_empty() && !video_ids.is_empty() => Err(ExtrasError::NoItemsReturned(
+            video_ids.iter().map(|id| id.to_string()).collect(),
+        )),
         Some(v) => {
-            if v.len() == videos.len() {
+            if v.len() == video_ids.len() {
                 v.into_iter()
                     .map(|v| {
+                        let snippet = v.snippet.unwrap_or_default();
+                        let chapters = snippet
+                            .description
+                            .as_deref()
+                            .map(parse_chapters)
+                            .unwrap_or_default();
+                        let content_details =
+                            v.content_details.ok_or(MissingContent::ContentDetails)?;
                         Ok(VideoExtras {
-                            duration: v
-                                .content_details
-                                .ok_or(MissingContent::ContentDetails)?
+                            duration: content_details
                                 .duration
                                 .ok_or(MissingContent::VideoDuration)?,
+                            age_restricted: content_details
+                                .content_rating
+                                .and_then(|r| r.yt_rating)
+                                .is_some_and(|r| r == "ytAgeRestricted"),
+                            title: snippet.title,
+                            channel_title: snippet.channel_title,
+                            privacy_status: v
+                                .status
+                                .and_then(|s| s.privacy_status)
+                                .ok_or(MissingContent::VideoPrivacyStatus)?,
+                            statistics: v.statistics.map(|s| VideoStats {
+                                views: s.view_count,
+                                likes: s.like_count,
+                            }),
+                            chapters,
                         })
                     })
                     .collect::<Result<Vec<VideoExtras>, ExtrasError>>()
@@ -222,11 +831,137 @@ pub async fn get_videos_extras(videos: &[Video]) -> Result<Vec<VideoExtras>, Ext
             }
         }
         None => {
-            if videos.len() == 0 {
+            if video_ids.len() == 0 {
                 Ok(vec![])
             } else {
-                Err(ExtrasError::Empty(response.0))
+                Err(ExtrasError::NoItemsReturned(
+                    video_ids.iter().map(|id| id.to_string()).collect(),
+                ))
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized_path(input: &str) -> Option<String> {
+        channel_page_path(normalize_channel_url(input).unwrap().path())
+    }
+
+    #[test]
+    fn channel_page_path_keeps_only_the_channel() {
+        assert_eq!(
+            normalized_path("https://www.youtube.com/user/someone/videos"),
+            Some("/user/someone".to_string())
+        );
+        assert_eq!(
+            normalized_path("youtube.com/c/SomeChannel/featured"),
+            Some("/c/SomeChannel".to_string())
+        );
+        assert_eq!(
+            normalized_path("https://m.youtube.com/channel/UCabcdefghijklmnopqrstuv/"),
+            Some("/channel/UCabcdefghijklmnopqrstuv".to_string())
+        );
+        assert_eq!(
+            normalized_path("  https://www.youtube.com/@handle/streams?si=tracking  "),
+            Some("/@handle".to_string())
+        );
+        assert_eq!(normalized_path("https://www.youtube.com/"), None);
+        assert_eq!(normalized_path("https://www.youtube.com/user/"), None);
+    }
+
+    #[test]
+    fn normalize_channel_url_rejects_other_hosts() {
+        assert!(matches!(
+            normalize_channel_url("https://example.com/@handle"),
+            Err(PlaylistIdError::NotYouTube(_))
+        ));
+        assert_eq!(
+            normalize_channel_url("YouTube.com/@handle?feature=share")
+                .unwrap()
+                .to_string(),
+            "https://www.youtube.com/@handle"
+        );
+    }
+
+    #[test]
+    fn uploads_playlist_id_swaps_prefix() {
+        assert_eq!(
+            uploads_playlist_id("UCabcdefghijklmnopqrstuv"),
+            Some("UUabcdefghijklmnopqrstuv".to_string())
+        );
+        assert_eq!(uploads_playlist_id("UCtooshort"), None);
+        assert_eq!(uploads_playlist_id("XXabcdefghijklmnopqrstuv"), None);
+        assert_eq!(uploads_playlist_id("UCabcdefghijklmnopqrst!v"), None);
+    }
+
+    // Trimmed down from real /search pages, keeping only what the scraper looks at
+    const RSS_LINK_PAGE: &str = r#"<link rel="alternate" type="application/rss+xml" title="RSS" href="https://www.youtube.com/feeds/videos.xml?channel_id=UCabcdefghijklmnopqrstuv"><script>var ytInitialData = {"metadata":{"channelMetadataRenderer":{"externalId":"UCzyxwvutsrqponmlkjihgfe"}}};</script>"#;
+    const EXTERNAL_ID_PAGE: &str = r#"<script>var ytInitialData = {"header":{"browseId":"UCzyxwvutsrqponmlkjihgfe"},"metadata":{"channelMetadataRenderer":{"title":"Someone","externalId":"UCabcdefghijklmnopqrstuv"}}};</script>"#;
+    const BROWSE_ID_PAGE: &str = r#"<script>var ytInitialData = {"contents":{"tabs":[{"endpoint":{"browseEndpoint":{"browseId":"UCabcdefghijklmnopqrstuv","params":"EgZzZWFyY2g"}}}]}};</script>"#;
+    const NO_ID_PAGE: &str = r#"<html><head><title>YouTube</title></head><body><script>var ytInitialData = {"contents":{"browseId":"FEwhat_to_watch"}};</script></body></html>"#;
+
+    fn scrape_fixture(page: &str) -> Result<String, PlaylistIdError> {
+        playlist_id_from_page(
+            page.as_bytes(),
+            "https://www.youtube.com/@someone/search".to_string(),
+        )
+    }
+
+    #[test]
+    fn scan_for_channel_id_reads_up_to_the_quote() {
+        assert_eq!(
+            scan_for_channel_id(RSS_LINK_PAGE.as_bytes(), CHANNEL_ID_PREFIXES[0]),
+            Some("UCabcdefghijklmnopqrstuv".to_string())
+        );
+        assert_eq!(
+            scan_for_channel_id(EXTERNAL_ID_PAGE.as_bytes(), CHANNEL_ID_PREFIXES[0]),
+            None
+        );
+        assert_eq!(
+            scan_for_channel_id(NO_ID_PAGE.as_bytes(), CHANNEL_ID_PREFIXES[2]),
+            None
+        );
+    }
+
+    #[test]
+    fn playlist_id_from_page_falls_back_in_order() {
+        // channel_id= wins over the externalId later in the page
+        assert_eq!(
+            scrape_fixture(RSS_LINK_PAGE).unwrap(),
+            "UUabcdefghijklmnopqrstuv"
+        );
+        // externalId wins over an earlier browseId
+        assert_eq!(
+            scrape_fixture(EXTERNAL_ID_PAGE).unwrap(),
+            "UUabcdefghijklmnopqrstuv"
+        );
+        assert_eq!(
+            scrape_fixture(BROWSE_ID_PAGE).unwrap(),
+            "UUabcdefghijklmnopqrstuv"
+        );
+        assert!(matches!(
+            scrape_fixture(NO_ID_PAGE),
+            Err(PlaylistIdError::BodyParseError(uri)) if uri == "https://www.youtube.com/@someone/search"
+        ));
+    }
+
+    // /channel/ urls are answered without touching the network or the config
+    #[tokio::test]
+    async fn channel_urls_short_circuit() {
+        assert_eq!(
+            get_upload_playlist_id(
+                "https://www.youtube.com/channel/UCabcdefghijklmnopqrstuv/videos"
+            )
+            .await
+            .unwrap(),
+            "UUabcdefghijklmnopqrstuv"
+        );
+        assert!(matches!(
+            get_upload_playlist_id("https://www.youtube.com/channel/not-a-channel-id").await,
+            Err(PlaylistIdError::NotAChannelUrl(_))
+        ));
+    }
+}